@@ -0,0 +1,158 @@
+use crate::ast::{self, Expr};
+use crate::bdd::{Bdd, Node};
+use crate::minimize;
+use crate::utils::collect_vars;
+
+use std::hash::Hash;
+
+/// Returns the variables which the value of `expr` depends on, in the order of their first appearance.
+///
+/// A variable is omitted if it only appears in redundant positions, e.g. `1` in `any(0, all(0, 1))`.
+pub fn support<T>(expr: &Expr<T>) -> Vec<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut bdd = Bdd::new();
+    let f = bdd.from_expr(expr);
+    collect_vars(expr)
+        .into_iter()
+        .filter(|&var| derivative(&mut bdd, f, var) != Node::FALSE)
+        .cloned()
+        .collect()
+}
+
+/// Returns the sensitivity of `expr` to each of its variables:
+/// the fraction of the assignments of the other variables under which flipping the variable flips `expr`.
+///
+/// Variables outside of the [`support`] have a sensitivity of `0.0`.
+pub fn sensitivity<T>(expr: &Expr<T>) -> Vec<(T, f64)>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut bdd = Bdd::new();
+    let f = bdd.from_expr(expr);
+    collect_vars(expr)
+        .into_iter()
+        .map(|var| {
+            let d = derivative(&mut bdd, f, var);
+            (var.clone(), bdd.probability(d, &|_| 0.5))
+        })
+        .collect()
+}
+
+/// The boolean derivative `f(var = 0) xor f(var = 1)`.
+fn derivative<T>(bdd: &mut Bdd<T>, f: Node, var: &T) -> Node
+where
+    T: Eq + Hash + Clone,
+{
+    let low = bdd.restrict(f, var, false);
+    let high = bdd.restrict(f, var, true);
+    let not_low = bdd.not(low);
+    let not_high = bdd.not(high);
+    let lhs = bdd.and(low, not_high);
+    let rhs = bdd.and(high, not_low);
+    bdd.or(lhs, rhs)
+}
+
+/// A product term which implies the expression and does so no longer if any literal is removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrimeImplicant<T> {
+    pub literals: Vec<(T, bool)>,
+    /// Whether some satisfying assignment is covered by no other prime implicant,
+    /// so that every minimal sum-of-products form contains this term.
+    pub essential: bool,
+}
+
+impl<T: Clone> PrimeImplicant<T> {
+    pub fn to_expr(&self) -> Expr<T> {
+        let mut lits: Vec<Expr<T>> = self
+            .literals
+            .iter()
+            .map(|(var, val)| {
+                let x = ast::expr(ast::var(var.clone()));
+                if *val {
+                    x
+                } else {
+                    ast::expr(ast::not(x))
+                }
+            })
+            .collect();
+        match lits.len() {
+            0 => Expr::Const(true),
+            1 => lits.pop().unwrap(),
+            _ => ast::expr(ast::all(lits)),
+        }
+    }
+}
+
+/// Returns the prime implicants of `expr`.
+///
+/// Returns `None` if `expr` has more than 16 variables,
+/// or if merging its minterms would generate too many implicants.
+pub fn prime_implicants<T>(expr: &Expr<T>) -> Option<Vec<PrimeImplicant<T>>>
+where
+    T: Eq + Hash + Clone,
+{
+    let vars = collect_vars(expr);
+    let primes = minimize::prime_implicants(expr, &vars)?
+        .into_iter()
+        .map(|(cube, essential)| PrimeImplicant {
+            literals: cube.into_iter().map(|(v, b)| (vars[v].clone(), b)).collect(),
+            essential,
+        })
+        .collect();
+    Some(primes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    #[test]
+    fn support_and_sensitivity() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        let x: Expr<u32> = expr(all((any((x0, all((x0, x1)))), any((x2, not(x2))))));
+        assert_eq!(support(&x), [0]);
+
+        let x: Expr<u32> = expr(any((all((x0, x1)), x2)));
+        assert_eq!(support(&x), [0, 1, 2]);
+        assert_eq!(sensitivity(&x), [(0, 0.25), (1, 0.25), (2, 0.75)]);
+
+        let x: Expr<u32> = expr(any((x0, all((x0, x1)))));
+        assert_eq!(sensitivity(&x), [(0, 1.0), (1, 0.0)]);
+    }
+
+    #[test]
+    fn primes() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        // the consensus term `all(1, 2)` is prime but not essential
+        let x: Expr<u32> = expr(any((all((x0, x1)), all((not(x0), x2)))));
+        let primes = prime_implicants(&x).unwrap();
+        let primes: Vec<_> = primes.iter().map(|p| (p.to_expr().to_string(), p.essential)).collect();
+        assert_eq!(
+            primes,
+            [
+                ("all(not(0), 2)".to_owned(), true),
+                ("all(0, 1)".to_owned(), true),
+                ("all(1, 2)".to_owned(), false),
+            ]
+        );
+
+        assert_eq!(
+            prime_implicants(&expr(any((x0, not(x0))))).unwrap()[0].to_expr(),
+            Expr::Const(true)
+        );
+        assert!(prime_implicants(&expr(all((x0, not(x0))))).unwrap().is_empty());
+
+        let x: Expr<u32> = expr(all((0..24).map(|i| expr(var(i))).collect::<Vec<_>>()));
+        assert!(prime_implicants(&x).is_none());
+
+        let mut terms: Vec<Expr<u32>> = (0..16).map(|i| expr(var(i))).collect();
+        terms.push(expr(not(x0)));
+        assert!(prime_implicants(&expr(any(terms))).is_none());
+    }
+}
@@ -0,0 +1,362 @@
+use crate::ast::{self, All, Any, Expr, Var};
+use crate::visit::postorder_with;
+use crate::visit_mut::{traverse_mut, VisitMut};
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::slice;
+
+/// A handle of an interned expression.
+///
+/// Two ids of the same arena are equal if and only if the expressions are structurally equal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ExprId(u32);
+
+/// An interned expression node whose children are ids.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Node<T> {
+    Any(Vec<ExprId>),
+    All(Vec<ExprId>),
+    Not(ExprId),
+    Var(T),
+    Const(bool),
+}
+
+impl<T> Node<T> {
+    pub fn operands(&self) -> &[ExprId] {
+        match self {
+            Node::Any(list) | Node::All(list) => list,
+            Node::Not(not) => slice::from_ref(not),
+            Node::Var(_) | Node::Const(_) => &[],
+        }
+    }
+}
+
+/// A hash-consing arena of expressions.
+///
+/// The children of a node are ids, so interning a node hashes only the node itself,
+/// no matter how deep the expression is.
+#[derive(Debug)]
+pub struct Arena<T> {
+    nodes: Vec<Node<T>>,
+    ids: HashMap<Node<T>, ExprId>,
+}
+
+impl<T> Arena<T>
+where
+    T: Hash + Eq + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn get(&self, id: ExprId) -> &Node<T> {
+        &self.nodes[id.0 as usize]
+    }
+
+    /// Returns the id of `node`, adding it to the arena if it is new.
+    pub fn intern(&mut self, node: Node<T>) -> ExprId {
+        if let Some(&id) = self.ids.get(&node) {
+            return id;
+        }
+        let id = ExprId(self.nodes.len() as u32);
+        self.nodes.push(node.clone());
+        self.ids.insert(node, id);
+        id
+    }
+
+    /// Interns `expr` whose operands have been interned as `operands`.
+    fn intern_shallow(&mut self, expr: &Expr<T>, mut operands: Vec<ExprId>) -> ExprId {
+        let node = match expr {
+            Expr::Any(_) => Node::Any(operands),
+            Expr::All(_) => Node::All(operands),
+            Expr::Not(_) => Node::Not(operands.pop().unwrap()),
+            Expr::Var(Var(var)) => Node::Var(var.clone()),
+            Expr::Const(b) => Node::Const(*b),
+        };
+        self.intern(node)
+    }
+
+    pub fn insert(&mut self, expr: &Expr<T>) -> ExprId {
+        postorder_with(expr, |expr, operands| self.intern_shallow(expr, operands))
+    }
+
+    pub fn to_expr(&self, id: ExprId) -> Expr<T> {
+        // the ids being built with the operands built so far
+        let mut stack: Vec<(ExprId, Vec<Expr<T>>)> = vec![(id, Vec::new())];
+        loop {
+            let (id, done) = stack.last().unwrap();
+            let operands = self.get(*id).operands();
+            if let Some(&next) = operands.get(done.len()) {
+                stack.push((next, Vec::with_capacity(self.get(next).operands().len())));
+                continue;
+            }
+
+            let (id, mut done) = stack.pop().unwrap();
+            let expr = match self.get(id) {
+                Node::Any(_) => ast::expr(ast::any(done)),
+                Node::All(_) => ast::expr(ast::all(done)),
+                Node::Not(_) => ast::expr(ast::not(done.pop().unwrap())),
+                Node::Var(var) => ast::expr(ast::var(var.clone())),
+                Node::Const(b) => Expr::Const(*b),
+            };
+            match stack.last_mut() {
+                Some((_, parent)) => parent.push(expr),
+                None => return expr,
+            }
+        }
+    }
+
+    /// Calls `f` on every subexpression of `expr` after its operands, with the ids of the operands,
+    /// and returns the id of `expr` as `f` leaves it.
+    ///
+    /// `f` must keep the ids in step with the operands of the subexpression.
+    pub(crate) fn postorder_mut<F>(&mut self, expr: &mut Expr<T>, mut f: F) -> ExprId
+    where
+        F: FnMut(&mut Self, &mut Expr<T>, &mut Vec<ExprId>),
+    {
+        traverse_mut(
+            expr,
+            |_| true,
+            |expr, mut operands, _| {
+                f(self, expr, &mut operands);
+                self.intern_shallow(expr, operands)
+            },
+        )
+    }
+}
+
+impl<T> Default for Arena<T>
+where
+    T: Hash + Eq + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Keeps the elements whose flags are `true`.
+fn retain_by<T>(list: &mut Vec<T>, keep: &[bool]) {
+    let mut keep = keep.iter();
+    list.retain(|_| *keep.next().unwrap());
+}
+
+/// [`DedupList`](crate::transform::DedupList) which compares the operands by their ids in an [`Arena`].
+pub struct DedupListByArena;
+
+impl DedupListByArena {
+    fn run<T: Hash + Eq + Clone>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        Arena::new().postorder_mut(expr, |_, expr, ids| {
+            if let Some(list) = expr.as_mut_expr_list() {
+                let mut seen = HashSet::new();
+                let keep: Vec<bool> = ids.iter().map(|&id| seen.insert(id)).collect();
+                if keep.contains(&false) {
+                    retain_by(list, &keep);
+                    retain_by(ids, &keep);
+                    changed = true;
+                }
+            }
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for DedupListByArena
+where
+    T: Hash + Eq + Clone,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
+    }
+}
+
+/// [`SimplifyNestedList`](crate::transform::SimplifyNestedList) which compares the operands
+/// by their ids in an [`Arena`].
+pub struct SimplifyNestedListByArena;
+
+impl SimplifyNestedListByArena {
+    /// Removes the first inner list which shares an element with the outer list.
+    fn remove_absorbed<T: Hash + Eq + Clone>(
+        arena: &Arena<T>,
+        list: &mut Vec<Expr<T>>,
+        ids: &mut Vec<ExprId>,
+        as_inner: fn(&Node<T>) -> Option<&[ExprId]>,
+    ) -> bool {
+        let outer: HashSet<ExprId> = ids.iter().copied().collect();
+        let pos = ids
+            .iter()
+            .position(|&x| matches!(as_inner(arena.get(x)), Some(inner) if inner.iter().any(|y| outer.contains(y))));
+        match pos {
+            Some(i) => {
+                list.remove(i);
+                ids.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn run<T: Hash + Eq + Clone>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        Arena::new().postorder_mut(expr, |arena, expr, ids| match expr {
+            Expr::Any(Any(any)) => {
+                while Self::remove_absorbed(arena, any, ids, |x| match x {
+                    Node::All(all) => Some(all),
+                    _ => None,
+                }) {
+                    changed = true;
+                }
+            }
+            Expr::All(All(all)) => {
+                while Self::remove_absorbed(arena, all, ids, |x| match x {
+                    Node::Any(any) => Some(any),
+                    _ => None,
+                }) {
+                    changed = true;
+                }
+            }
+            _ => {}
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for SimplifyNestedListByArena
+where
+    T: Hash + Eq + Clone,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
+    }
+}
+
+/// [`MergeAllOfAny`](crate::transform::MergeAllOfAny) which compares the operands by their ids
+/// in an [`Arena`].
+pub struct MergeAllOfAnyByArena;
+
+impl MergeAllOfAnyByArena {
+    fn is_subset_of<T: Hash + Eq + Clone>(arena: &Arena<T>, lhs: ExprId, rhs: ExprId) -> bool {
+        let rhs: HashSet<ExprId> = arena.get(rhs).operands().iter().copied().collect();
+        arena.get(lhs).operands().iter().all(|x| rhs.contains(x))
+    }
+
+    fn merge<T: Hash + Eq + Clone>(arena: &mut Arena<T>, all: &mut [Expr<T>], ids: &mut [ExprId]) -> bool {
+        let mut changed = false;
+        let any_list: Vec<usize> = (0..all.len()).filter(|&i| all[i].is_any()).collect();
+
+        for &i in &any_list {
+            for &j in &any_list {
+                if i != j && Self::is_subset_of(arena, ids[i], ids[j]) {
+                    let rhs = all[j].as_mut_any().unwrap();
+                    rhs.0.clear();
+                    rhs.0.push(Expr::Const(true));
+
+                    let true_ = arena.intern(Node::Const(true));
+                    let id = arena.intern(Node::Any(vec![true_]));
+                    changed |= ids[j] != id;
+                    ids[j] = id;
+                }
+            }
+        }
+        changed
+    }
+
+    fn run<T: Hash + Eq + Clone>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        Arena::new().postorder_mut(expr, |arena, expr, ids| {
+            if let Expr::All(All(all)) = expr {
+                changed |= Self::merge(arena, all, ids);
+            }
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for MergeAllOfAnyByArena
+where
+    T: Hash + Eq + Clone,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+    use crate::transform::{DedupList, MergeAllOfAny, SimplifyNestedList};
+
+    #[test]
+    fn sharing() {
+        let (x0, x1) = (var(0), var(1));
+        let a = expr(all((x0, not(x1))));
+        let src = expr(any((a.clone(), not(a.clone()), a)));
+
+        let mut arena = Arena::new();
+        let id = arena.insert(&src);
+        assert_eq!(arena.len(), 6);
+        assert_eq!(arena.to_expr(id), src);
+
+        let Node::Any(list) = arena.get(id).clone() else {
+            panic!()
+        };
+        assert_eq!(list[0], list[2]);
+        assert_eq!(arena.get(list[1]), &Node::Not(list[0]));
+
+        let set: HashSet<ExprId> = list.iter().copied().collect();
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn list_passes() {
+        fn check(src: Expr<u32>, pass: &mut impl VisitMut<u32>, by_arena: &mut impl VisitMut<u32>) {
+            let (mut x, mut y) = (src.clone(), src);
+            pass.visit_mut_expr(&mut x);
+            by_arena.visit_mut_expr(&mut y);
+            assert_eq!(x, y);
+        }
+
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+        check(
+            expr(any((any((x0, x0)), any((x0,)), all((x1, x2, x1))))),
+            &mut DedupList,
+            &mut DedupListByArena,
+        );
+        check(
+            expr(any((x0, all((x1, any((x2, all((x2, x0)))))), all((x0, x1))))),
+            &mut SimplifyNestedList,
+            &mut SimplifyNestedListByArena,
+        );
+        check(
+            expr(all((any((x0, x1)), any((x1, x2, x0)), not(any((x0,)))))),
+            &mut MergeAllOfAny,
+            &mut MergeAllOfAnyByArena,
+        );
+    }
+}
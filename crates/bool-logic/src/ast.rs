@@ -1,6 +1,26 @@
-use std::fmt;
+use crate::fold::try_postorder_into;
+use crate::rewrite::ParseError;
+use crate::visit::{postorder_with, preorder};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::convert::Infallible;
+use core::fmt::{self, Write as _};
+use core::hash::{Hash, Hasher};
+use core::mem;
+use core::slice;
+use core::str::FromStr;
+
+/// A boolean expression.
+///
+/// `Clone`, `PartialEq`, `Ord`, `Hash` and `Debug` are implemented without recursion,
+/// so they work on arbitrarily deep expressions. They behave like the derived implementations,
+/// except that `Hash` feeds the hasher a different sequence.
+///
+/// [`Any`], [`All`] and [`Not`] implement `Drop` for the same reason,
+/// so their operands are moved out by `into_inner` rather than by a pattern.
 pub enum Expr<T> {
     Any(Any<T>),
     All(All<T>),
@@ -9,18 +29,340 @@ pub enum Expr<T> {
     Const(bool),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A disjunction.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Any<T>(pub Vec<Expr<T>>);
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A conjunction.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct All<T>(pub Vec<Expr<T>>);
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A negation.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Not<T>(pub Box<Expr<T>>);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Var<T>(pub T);
 
+impl<T> Any<T> {
+    /// Moves the operands out.
+    pub fn into_inner(mut self) -> Vec<Expr<T>> {
+        mem::take(&mut self.0)
+    }
+}
+
+impl<T> All<T> {
+    /// Moves the operands out.
+    pub fn into_inner(mut self) -> Vec<Expr<T>> {
+        mem::take(&mut self.0)
+    }
+}
+
+impl<T> Not<T> {
+    /// Moves the operand out.
+    pub fn into_inner(mut self) -> Expr<T> {
+        mem::replace(&mut self.0, Expr::Const(false))
+    }
+}
+
+// The default drop glue recurses into every level of the tree.
+// These implementations detach the nested operands onto a heap stack instead,
+// so that very deep expressions can be dropped without overflowing the call stack.
+
+impl<T> Drop for Any<T> {
+    fn drop(&mut self) {
+        if self.0.iter().any(has_nested) {
+            drop_iterative(mem::take(&mut self.0));
+        }
+    }
+}
+
+impl<T> Drop for All<T> {
+    fn drop(&mut self) {
+        if self.0.iter().any(has_nested) {
+            drop_iterative(mem::take(&mut self.0));
+        }
+    }
+}
+
+impl<T> Drop for Not<T> {
+    fn drop(&mut self) {
+        if has_nested(&self.0) {
+            drop_iterative(vec![mem::replace(&mut self.0, Expr::Const(false))]);
+        }
+    }
+}
+
+/// Returns `true` if `expr` has an operand which has operands itself.
+fn has_nested<T>(expr: &Expr<T>) -> bool {
+    let is_leaf = |x: &Expr<T>| match x {
+        Expr::Any(Any(list)) | Expr::All(All(list)) => list.is_empty(),
+        Expr::Not(_) => false,
+        Expr::Var(_) | Expr::Const(_) => true,
+    };
+    match expr {
+        Expr::Any(Any(list)) | Expr::All(All(list)) => !list.iter().all(is_leaf),
+        Expr::Not(Not(not)) => !is_leaf(not),
+        Expr::Var(_) | Expr::Const(_) => false,
+    }
+}
+
+fn drop_iterative<T>(mut stack: Vec<Expr<T>>) {
+    while let Some(mut expr) = stack.pop() {
+        match &mut expr {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => stack.append(list),
+            Expr::Not(Not(not)) => stack.push(mem::replace(&mut **not, Expr::Const(false))),
+            Expr::Var(_) | Expr::Const(_) => {}
+        }
+        // `expr` has no nested operands now, so dropping it does not recurse.
+    }
+}
+
+impl<T: Clone> Clone for Expr<T> {
+    fn clone(&self) -> Self {
+        postorder_with(self, |expr, mut operands| match expr {
+            Expr::Any(_) => Expr::Any(Any(operands)),
+            Expr::All(_) => Expr::All(All(operands)),
+            Expr::Not(_) => Expr::Not(Not(Box::new(operands.pop().unwrap()))),
+            Expr::Var(var) => Expr::Var(var.clone()),
+            Expr::Const(b) => Expr::Const(*b),
+        })
+    }
+}
+
+impl<T: PartialEq> PartialEq for Expr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some(pair) = stack.pop() {
+            match pair {
+                (Expr::Any(Any(lhs)), Expr::Any(Any(rhs))) | (Expr::All(All(lhs)), Expr::All(All(rhs))) => {
+                    if lhs.len() != rhs.len() {
+                        return false;
+                    }
+                    stack.extend(lhs.iter().zip(rhs));
+                }
+                (Expr::Not(Not(lhs)), Expr::Not(Not(rhs))) => stack.push((lhs, rhs)),
+                (Expr::Var(Var(lhs)), Expr::Var(Var(rhs))) => {
+                    if lhs != rhs {
+                        return false;
+                    }
+                }
+                (Expr::Const(lhs), Expr::Const(rhs)) => {
+                    if lhs != rhs {
+                        return false;
+                    }
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl<T: Eq> Eq for Expr<T> {}
+
+impl<T: PartialOrd> PartialOrd for Expr<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        compare(self, other, T::partial_cmp)
+    }
+}
+
+impl<T: Ord> Ord for Expr<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self, other, |lhs, rhs| Some(lhs.cmp(rhs))).unwrap()
+    }
+}
+
+/// Compares in the order of the derived implementations:
+/// by variant first, then by operands lexicographically.
+fn compare<T, F>(lhs: &Expr<T>, rhs: &Expr<T>, mut cmp_var: F) -> Option<Ordering>
+where
+    F: FnMut(&T, &T) -> Option<Ordering>,
+{
+    enum Item<'a, T> {
+        Pair(&'a Expr<T>, &'a Expr<T>),
+        /// Decides between two lists whose common prefix is equal.
+        Len(Ordering),
+    }
+
+    fn variant<T>(expr: &Expr<T>) -> u8 {
+        match expr {
+            Expr::Any(_) => 0,
+            Expr::All(_) => 1,
+            Expr::Not(_) => 2,
+            Expr::Var(_) => 3,
+            Expr::Const(_) => 4,
+        }
+    }
+
+    let mut stack = vec![Item::Pair(lhs, rhs)];
+    while let Some(item) = stack.pop() {
+        let ord = match item {
+            Item::Len(ord) => ord,
+            Item::Pair(Expr::Any(Any(lhs)), Expr::Any(Any(rhs)))
+            | Item::Pair(Expr::All(All(lhs)), Expr::All(All(rhs))) => {
+                stack.push(Item::Len(lhs.len().cmp(&rhs.len())));
+                stack.extend(lhs.iter().zip(rhs).rev().map(|(lhs, rhs)| Item::Pair(lhs, rhs)));
+                continue;
+            }
+            Item::Pair(Expr::Not(Not(lhs)), Expr::Not(Not(rhs))) => {
+                stack.push(Item::Pair(lhs, rhs));
+                continue;
+            }
+            Item::Pair(Expr::Var(Var(lhs)), Expr::Var(Var(rhs))) => cmp_var(lhs, rhs)?,
+            Item::Pair(Expr::Const(lhs), Expr::Const(rhs)) => lhs.cmp(rhs),
+            Item::Pair(lhs, rhs) => variant(lhs).cmp(&variant(rhs)),
+        };
+        if ord.is_ne() {
+            return Some(ord);
+        }
+    }
+    Some(Ordering::Equal)
+}
+
+impl<T: Hash> Hash for Expr<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for expr in preorder(self) {
+            mem::discriminant(expr).hash(state);
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => list.len().hash(state),
+                Expr::Not(_) => {}
+                Expr::Var(Var(var)) => var.hash(state),
+                Expr::Const(b) => b.hash(state),
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Expr<T> {
+    /// Prints like the derived implementation, e.g. `Not(Not(Var(Var(0))))`, also with `{:#?}`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        enum Item<'a, T> {
+            Expr(&'a Expr<T>),
+            Var(&'a T),
+            Str(&'static str),
+            Indent,
+            Dedent,
+        }
+
+        let pretty = f.alternate();
+        let open = |items: &mut Vec<Item<'_, T>>, name: &'static str| {
+            items.push(Item::Str(name));
+            items.push(Item::Str(if pretty { "(\n" } else { "(" }));
+            items.push(Item::Indent);
+        };
+        let close = |items: &mut Vec<Item<'_, T>>| {
+            items.push(Item::Dedent);
+            items.push(Item::Str(if pretty { ",\n)" } else { ")" }));
+        };
+
+        // the pending output in reverse order
+        let mut stack = vec![Item::Expr(self)];
+        let mut w = PadAdapter {
+            f,
+            depth: 0,
+            on_newline: false,
+        };
+        while let Some(item) = stack.pop() {
+            let expr = match item {
+                Item::Expr(expr) => expr,
+                Item::Var(var) if pretty => {
+                    write!(w, "{var:#?}")?;
+                    continue;
+                }
+                Item::Var(var) => {
+                    write!(w, "{var:?}")?;
+                    continue;
+                }
+                Item::Str(s) => {
+                    w.write_str(s)?;
+                    continue;
+                }
+                Item::Indent => {
+                    w.depth += 1;
+                    continue;
+                }
+                Item::Dedent => {
+                    w.depth -= 1;
+                    continue;
+                }
+            };
+
+            let mut items = Vec::new();
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    let name = if expr.is_any() { "Any" } else { "All" };
+                    open(&mut items, name);
+                    open(&mut items, name);
+                    items.push(Item::Str("["));
+                    if pretty && !list.is_empty() {
+                        items.push(Item::Str("\n"));
+                        items.push(Item::Indent);
+                        for e in list {
+                            items.push(Item::Expr(e));
+                            items.push(Item::Str(",\n"));
+                        }
+                        items.push(Item::Dedent);
+                    } else {
+                        for (i, e) in list.iter().enumerate() {
+                            if i != 0 {
+                                items.push(Item::Str(", "));
+                            }
+                            items.push(Item::Expr(e));
+                        }
+                    }
+                    items.push(Item::Str("]"));
+                    close(&mut items);
+                    close(&mut items);
+                }
+                Expr::Not(Not(not)) => {
+                    open(&mut items, "Not");
+                    open(&mut items, "Not");
+                    items.push(Item::Expr(not));
+                    close(&mut items);
+                    close(&mut items);
+                }
+                Expr::Var(Var(var)) => {
+                    open(&mut items, "Var");
+                    open(&mut items, "Var");
+                    items.push(Item::Var(var));
+                    close(&mut items);
+                    close(&mut items);
+                }
+                Expr::Const(b) => {
+                    open(&mut items, "Const");
+                    items.push(Item::Str(if *b { "true" } else { "false" }));
+                    close(&mut items);
+                }
+            }
+            stack.extend(items.into_iter().rev());
+        }
+        Ok(())
+    }
+}
+
+/// Indents every line written through it, like the adapter behind `{:#?}`.
+struct PadAdapter<'a, 'b> {
+    f: &'a mut fmt::Formatter<'b>,
+    depth: usize,
+    on_newline: bool,
+}
+
+impl fmt::Write for PadAdapter<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for line in s.split_inclusive('\n') {
+            if self.on_newline {
+                for _ in 0..self.depth {
+                    self.f.write_str("    ")?;
+                }
+            }
+            self.on_newline = line.ends_with('\n');
+            self.f.write_str(line)?;
+        }
+        Ok(())
+    }
+}
+
 pub fn expr<T>(x: impl Into<Expr<T>>) -> Expr<T> {
     x.into()
 }
@@ -174,28 +516,139 @@ where
     T: fmt::Display,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Expr::Any(Any(any)) => fmt_list(f, "any", any),
-            Expr::All(All(all)) => fmt_list(f, "all", all),
-            Expr::Not(Not(not)) => write!(f, "not({not})"),
-            Expr::Var(Var(x)) => write!(f, "{x}"),
-            Expr::Const(b) => write!(f, "{b}"),
+        enum Item<'a, T> {
+            Expr(&'a Expr<T>),
+            Str(&'static str),
         }
+
+        // the pending output in reverse order
+        let mut stack = vec![Item::Expr(self)];
+        while let Some(item) = stack.pop() {
+            let expr = match item {
+                Item::Expr(expr) => expr,
+                Item::Str(s) => {
+                    f.write_str(s)?;
+                    continue;
+                }
+            };
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                    f.write_str(if expr.is_any() { "any(" } else { "all(" })?;
+                    stack.push(Item::Str(")"));
+                    for (i, e) in list.iter().enumerate().rev() {
+                        stack.push(Item::Expr(e));
+                        if i != 0 {
+                            stack.push(Item::Str(", "));
+                        }
+                    }
+                }
+                Expr::Not(Not(not)) => {
+                    f.write_str("not(")?;
+                    stack.push(Item::Str(")"));
+                    stack.push(Item::Expr(not));
+                }
+                Expr::Var(Var(x)) => write!(f, "{x}")?,
+                Expr::Const(b) => write!(f, "{b}")?,
+            }
+        }
+        Ok(())
     }
 }
 
-fn fmt_list<T>(f: &mut fmt::Formatter<'_>, name: &str, list: &[Expr<T>]) -> fmt::Result
-where
-    T: fmt::Display,
-{
-    write!(f, "{name}(")?;
-    for (i, e) in list.iter().enumerate() {
-        if i != 0 {
-            write!(f, ", ")?;
+/// Parses the syntax printed by `Display`, such as `all(a, not(any(b, c)))`.
+///
+/// A variable is a run of characters other than whitespace and `(),`, parsed by [`FromStr`].
+/// For the infix syntax, see [`infix::parse`](crate::infix::parse).
+impl<T: FromStr> FromStr for Expr<T> {
+    type Err = ParseError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        enum Op {
+            Any,
+            All,
+            Not,
+        }
+
+        fn error<U>(pos: usize, msg: &'static str) -> Result<U, ParseError> {
+            Err(ParseError { pos, msg })
+        }
+
+        let skip_ws = |pos: usize| src.len() - src[pos..].trim_start().len();
+        let is_delim = |c: char| c.is_whitespace() || matches!(c, '(' | ')' | ',');
+
+        // the operators whose operands are being parsed
+        let mut stack: Vec<(Op, Vec<Expr<T>>)> = Vec::new();
+        let mut pos = 0;
+        loop {
+            pos = skip_ws(pos);
+            let start = pos;
+            pos += src[pos..].find(is_delim).unwrap_or(src.len() - pos);
+            let word = &src[start..pos];
+
+            let mut expr = if src[pos..].starts_with('(') {
+                let op = match word {
+                    "any" => Op::Any,
+                    "all" => Op::All,
+                    "not" => Op::Not,
+                    _ => return error(start, "expected `any`, `all` or `not`"),
+                };
+                pos = skip_ws(pos + 1);
+                match op {
+                    Op::Any | Op::All if src[pos..].starts_with(')') => {
+                        pos += 1;
+                        if matches!(op, Op::Any) {
+                            self::expr(any(()))
+                        } else {
+                            self::expr(all(()))
+                        }
+                    }
+                    _ => {
+                        stack.push((op, Vec::new()));
+                        continue;
+                    }
+                }
+            } else {
+                match word {
+                    "" => return error(start, "expected an expression"),
+                    "true" => Expr::Const(true),
+                    "false" => Expr::Const(false),
+                    _ => match word.parse() {
+                        Ok(x) => Expr::Var(Var(x)),
+                        Err(_) => return error(start, "invalid variable"),
+                    },
+                }
+            };
+
+            loop {
+                let Some((op, list)) = stack.last_mut() else {
+                    pos = skip_ws(pos);
+                    if pos != src.len() {
+                        return error(pos, "unexpected trailing input");
+                    }
+                    return Ok(expr);
+                };
+                list.push(expr);
+                pos = skip_ws(pos);
+                if !matches!(op, Op::Not) && src[pos..].starts_with(',') {
+                    pos += 1;
+                    break;
+                }
+                if !src[pos..].starts_with(')') {
+                    return match op {
+                        Op::Not => error(pos, "expected `)`"),
+                        _ => error(pos, "expected `,` or `)`"),
+                    };
+                }
+                pos += 1;
+                let (op, mut list) = stack.pop().unwrap();
+                expr = match op {
+                    Op::Any => self::expr(any(list)),
+                    Op::All => self::expr(all(list)),
+                    Op::Not => self::expr(not(list.pop().unwrap())),
+                };
+            }
         }
-        write!(f, "{e}")?;
     }
-    write!(f, ")")
 }
 
 impl<T> Expr<T> {
@@ -305,3 +758,267 @@ impl<T> Expr<T> {
         false
     }
 }
+
+impl<T> Expr<T> {
+    /// The operands of `any`, `all` and `not`, or nothing.
+    pub(crate) fn operands(&self) -> &[Expr<T>] {
+        match self {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => list,
+            Expr::Not(Not(not)) => slice::from_ref(not),
+            Expr::Var(_) | Expr::Const(_) => &[],
+        }
+    }
+
+    /// Converts every variable with `f`.
+    pub fn map_vars<U, F>(self, mut f: F) -> Expr<U>
+    where
+        F: FnMut(T) -> U,
+    {
+        match self.try_map_vars(|var| Ok::<_, Infallible>(f(var))) {
+            Ok(expr) => expr,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Converts every variable with `f`, stopping at the first error.
+    pub fn try_map_vars<U, E, F>(self, mut f: F) -> Result<Expr<U>, E>
+    where
+        F: FnMut(T) -> Result<U, E>,
+    {
+        try_postorder_into(self, |expr, mut operands| {
+            Ok(match expr {
+                Expr::Any(_) => Expr::Any(Any(operands)),
+                Expr::All(_) => Expr::All(All(operands)),
+                Expr::Not(_) => Expr::Not(Not(Box::new(operands.pop().unwrap()))),
+                Expr::Var(Var(var)) => Expr::Var(Var(f(var)?)),
+                Expr::Const(b) => Expr::Const(b),
+            })
+        })
+    }
+
+    /// Replaces every variable for which `f` returns `Some` with the returned expression.
+    pub fn substitute<F>(self, mut f: F) -> Expr<T>
+    where
+        F: FnMut(&T) -> Option<Expr<T>>,
+    {
+        let result = try_postorder_into(self, |expr, mut operands| {
+            Ok::<_, Infallible>(match expr {
+                Expr::Any(_) => Expr::Any(Any(operands)),
+                Expr::All(_) => Expr::All(All(operands)),
+                Expr::Not(_) => Expr::Not(Not(Box::new(operands.pop().unwrap()))),
+                Expr::Var(Var(var)) => match f(&var) {
+                    Some(expr) => expr,
+                    None => Expr::Var(Var(var)),
+                },
+                Expr::Const(b) => Expr::Const(b),
+            })
+        });
+        match result {
+            Ok(expr) => expr,
+            Err(never) => match never {},
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_vars() {
+        let x: Expr<&str> = expr(all((var("a"), not(any((var("b"), var("c")))))));
+
+        let ids = ["a", "b", "c"];
+        let y = x.clone().map_vars(|v| ids.iter().position(|x| *x == v).unwrap());
+        assert_eq!(y.to_string(), "all(0, not(any(1, 2)))");
+
+        let y: Result<Expr<u32>, _> = x.clone().try_map_vars(|v| v.parse::<u32>());
+        assert!(y.is_err());
+
+        let y = x.substitute(|v| (*v == "b").then(|| expr(all((var("d"), var("e"))))));
+        assert_eq!(y.to_string(), "all(a, not(any(all(d, e), c)))");
+    }
+
+    #[test]
+    fn from_str() {
+        let x: Expr<String> = expr(all((
+            var("a".to_string()),
+            not(any((var("b".to_string()), const_(false)))),
+            any(()),
+        )));
+        assert_eq!(x.to_string().parse::<Expr<String>>().unwrap(), x);
+
+        let x: Expr<u32> = " any( 0 ,not(1), all() ) ".parse().unwrap();
+        assert_eq!(x.to_string(), "any(0, not(1), all())");
+
+        let err = "all(0, x)".parse::<Expr<u32>>().unwrap_err();
+        assert_eq!(err.to_string(), "invalid variable at position 7");
+        let err = "not(0, 1)".parse::<Expr<u32>>().unwrap_err();
+        assert_eq!(err.to_string(), "expected `)` at position 5");
+        let err = "any(0".parse::<Expr<u32>>().unwrap_err();
+        assert_eq!(err.to_string(), "expected `,` or `)` at position 5");
+        let err = "xor(0, 1)".parse::<Expr<u32>>().unwrap_err();
+        assert_eq!(err.to_string(), "expected `any`, `all` or `not` at position 0");
+        let err = "0 && 1".parse::<Expr<u32>>().unwrap_err();
+        assert_eq!(err.to_string(), "unexpected trailing input at position 2");
+    }
+
+    #[test]
+    fn debug() {
+        let x: Expr<u32> = expr(any((all(()), not(var(1)), const_(true))));
+        assert_eq!(
+            format!("{x:?}"),
+            "Any(Any([All(All([])), Not(Not(Var(Var(1)))), Const(true)]))"
+        );
+        assert_eq!(
+            format!("{x:#?}"),
+            "\
+Any(
+    Any(
+        [
+            All(
+                All(
+                    [],
+                ),
+            ),
+            Not(
+                Not(
+                    Var(
+                        Var(
+                            1,
+                        ),
+                    ),
+                ),
+            ),
+            Const(
+                true,
+            ),
+        ],
+    ),
+)"
+        );
+    }
+
+    #[test]
+    fn deep() {
+        use crate::arena::Arena;
+        use crate::bdd;
+        use crate::diff::semantic_diff;
+        use crate::egraph::{AstSize, EGraph};
+        use crate::eval::{eval_in, eval_with, is_equivalent, is_satisfiable, partial_eval, probability};
+        use crate::normal_form::{cnf, nnf, tseitin};
+        use crate::pipeline::Pipeline;
+        use crate::transform::*;
+        use crate::visit::preorder;
+        use crate::visit_mut::postorder_mut;
+
+        use std::collections::hash_map::DefaultHasher;
+
+        const DEPTH: usize = 1_000_000;
+
+        // all(not(all(not(...all(not(0), 1)...), 1)), 1)
+        let mut x: Expr<u32> = expr(var(0));
+        for i in 0..DEPTH {
+            x = if i & 1 == 0 {
+                expr(not(x))
+            } else {
+                expr(all((x, var(1))))
+            };
+        }
+
+        let s = x.to_string();
+        assert!(s.starts_with("all(not(all(not("));
+        assert!(s.ends_with("), 1)), 1)"));
+        assert_eq!(s.len(), DEPTH / 2 * ("not()".len() + "all(, 1)".len()) + 1);
+        assert!(s.parse::<Expr<u32>>().unwrap() == x);
+
+        assert!(eval_with(&x, &|&v| v == 0 || v == 1));
+        assert!(!eval_with(&x, &|&v| v == 1));
+        assert!(eval_in::<bool, _, _>(&x, &|&v, pos| (v == 0 || v == 1) == pos));
+
+        assert_eq!(preorder(&x).count(), DEPTH + DEPTH / 2 + 1);
+
+        let hash = |x: &Expr<u32>| {
+            let mut h = DefaultHasher::new();
+            x.hash(&mut h);
+            h.finish()
+        };
+        let y = x.clone();
+        assert!(x == y);
+        assert_eq!(x.cmp(&y), Ordering::Equal);
+        assert_eq!(hash(&x), hash(&y));
+        assert!(format!("{y:?}").starts_with("All(All([Not(Not(All(All([Not("));
+
+        let y = y.map_vars(|v| v ^ 1);
+        assert!(x != y);
+        assert_eq!(x.cmp(&y), Ordering::Less);
+        assert_ne!(hash(&x), hash(&y));
+
+        assert_eq!(partial_eval(&x, &|_| Some(true)), Expr::Const(true));
+        assert_eq!(partial_eval(&x, &|&v| (v == 1).then_some(false)), Expr::Const(false));
+        let y = x.clone().substitute(|&v| (v == 0).then(|| expr(not(var(2)))));
+        assert!(eval_with(&y, &|&v| v == 1));
+
+        let mut y = x
+            .clone()
+            .substitute(|&v| (v == 1).then(|| expr(all((var(1), const_(true))))));
+        let stats = Pipeline::new()
+            .pass(EvalConst)
+            .pass(FlattenSingle)
+            .pass(FlattenNestedList)
+            .pass(DedupList)
+            .pass(SimplifyNestedList)
+            .pass(SimplifyAllNotAny)
+            .pass(MergeAllOfNotAny)
+            .pass(MergeAllOfAny)
+            .run(&mut y);
+        assert!(stats.fixpoint);
+        assert_eq!(stats.rounds, 2);
+        assert!(x == y);
+
+        let mut arena = Arena::new();
+        let id = arena.insert(&x);
+        assert_eq!(arena.len(), DEPTH + 2);
+        assert!(arena.to_expr(id) == x);
+
+        // the chain is equivalent to `all(0, 1)`
+        let x01 = expr(all((var(0), var(1))));
+        assert!(is_satisfiable(&x).is_ok());
+        assert!(is_equivalent(&x, &x01).is_ok());
+        assert_eq!(probability(&x, &|_| 0.5), 0.25);
+        assert_eq!(bdd::simplify(&x), x01);
+        let diff = semantic_diff(&x, &expr(var(0)));
+        assert!(diff.only_old.is_const_false());
+        assert_eq!(diff.only_new.to_string(), "all(0, not(1))");
+
+        let mut egraph = EGraph::new();
+        let id = egraph.add_expr(&x);
+        assert_eq!(egraph.num_classes(), DEPTH + 2);
+        let y = egraph.extract(id, &mut AstSize);
+        assert_eq!(preorder(&y).count(), preorder(&x).count());
+        assert!(eval_with(&y, &|&v| v == 0 || v == 1));
+        assert!(!eval_with(&y, &|&v| v == 1));
+
+        let y = nnf(x.clone());
+        assert!(eval_with(&y, &|&v| v == 0 || v == 1));
+        assert!(!eval_with(&y, &|&v| v == 0));
+        assert_eq!(
+            cnf(x.clone(), 16).unwrap().into_expr().to_string(),
+            "all(any(0, not(1)), 1)"
+        );
+        assert!(is_satisfiable(&tseitin(&x).into_expr()).is_ok());
+
+        postorder_mut(&mut x, |e| {
+            if let Expr::Var(Var(v)) = e {
+                *v += 1;
+            }
+        });
+        assert!(eval_with(&x, &|&v| v == 1 || v == 2));
+        assert_eq!(
+            preorder(&x).filter(|e| matches!(e, Expr::Var(Var(2)))).count(),
+            DEPTH / 2
+        );
+
+        drop(x);
+    }
+}
@@ -0,0 +1,343 @@
+use crate::ast::{self, All, Any, Expr, Var};
+use crate::transform::{EvalConst, FlattenSingle};
+use crate::visit::postorder_with;
+use crate::visit_mut::*;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Known relations between variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Axioms<T> {
+    at_most_one: Vec<Vec<T>>,
+    exactly_one: Vec<Vec<T>>,
+    implications: Vec<(T, T)>,
+}
+
+/// The literals implied by an expression.
+pub type Facts<T> = HashMap<T, bool>;
+
+impl<T> Axioms<T>
+where
+    T: Eq + Hash + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            at_most_one: Vec::new(),
+            exactly_one: Vec::new(),
+            implications: Vec::new(),
+        }
+    }
+
+    /// Declares that the variables are mutually exclusive.
+    pub fn at_most_one(mut self, group: impl IntoIterator<Item = T>) -> Self {
+        self.at_most_one.push(group.into_iter().collect());
+        self
+    }
+
+    /// Declares that exactly one of the variables is true.
+    pub fn exactly_one(mut self, group: impl IntoIterator<Item = T>) -> Self {
+        self.exactly_one.push(group.into_iter().collect());
+        self
+    }
+
+    /// Declares that `lhs` implies `rhs`.
+    pub fn implies(mut self, lhs: T, rhs: T) -> Self {
+        self.implications.push((lhs, rhs));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.at_most_one.is_empty() && self.exactly_one.is_empty() && self.implications.is_empty()
+    }
+
+    /// Returns the conjunction of all axioms.
+    pub fn to_expr(&self) -> Expr<T> {
+        let x = |v: &T| ast::expr(ast::var(v.clone()));
+        let not_x = |v: &T| ast::expr(ast::not(x(v)));
+
+        let mut list = Vec::new();
+        for group in self.at_most_one.iter().chain(&self.exactly_one) {
+            for (i, a) in group.iter().enumerate() {
+                for b in &group[i + 1..] {
+                    list.push(ast::expr(ast::any((not_x(a), not_x(b)))));
+                }
+            }
+        }
+        for group in &self.exactly_one {
+            list.push(ast::expr(ast::any(group.iter().map(x).collect::<Vec<_>>())));
+        }
+        for (a, b) in &self.implications {
+            list.push(ast::expr(ast::any((not_x(a), x(b)))));
+        }
+        ast::expr(ast::all(list))
+    }
+
+    /// Returns the literals implied by `expr` under the axioms,
+    /// or `None` if `expr` contradicts them.
+    pub fn implied(&self, expr: &Expr<T>) -> Option<Facts<T>> {
+        self.implied_with(expr, true)
+    }
+
+    /// Returns the facts of `expr` being true and of `expr` being false, in one bottom-up pass.
+    fn implied_both(&self, expr: &Expr<T>) -> [Option<Facts<T>>; 2] {
+        postorder_with(expr, |expr, mut operands: Vec<[Option<Facts<T>>; 2]>| match expr {
+            Expr::Any(_) | Expr::All(_) => {
+                let (neg, pos): (Vec<_>, Vec<_>) = operands.into_iter().map(|[neg, pos]| (neg, pos)).unzip();
+                let (or, and) = if expr.is_any() { (pos, neg) } else { (neg, pos) };
+                let (or, and) = (self.implied_any(or), self.implied_all(and));
+                if expr.is_any() {
+                    [and, or]
+                } else {
+                    [or, and]
+                }
+            }
+            Expr::Not(_) => {
+                let [neg, pos] = operands.pop().unwrap();
+                [pos, neg]
+            }
+            Expr::Var(Var(var)) => [false, true].map(|val| self.close(Facts::from([(var.clone(), val)]))),
+            Expr::Const(b) => [false, true].map(|val| (*b == val).then(Facts::new)),
+        })
+    }
+
+    fn implied_with(&self, expr: &Expr<T>, positive: bool) -> Option<Facts<T>> {
+        let [neg, pos] = self.implied_both(expr);
+        if positive {
+            pos
+        } else {
+            neg
+        }
+    }
+
+    /// The facts of a conjunction are the union of the facts of its conjuncts.
+    fn implied_all(&self, list: Vec<Option<Facts<T>>>) -> Option<Facts<T>> {
+        let mut facts = Facts::new();
+        for e in list {
+            for (var, val) in e? {
+                if *facts.entry(var).or_insert(val) != val {
+                    return None;
+                }
+            }
+        }
+        self.close(facts)
+    }
+
+    /// The facts of a disjunction are the facts shared by all satisfiable disjuncts.
+    fn implied_any(&self, list: Vec<Option<Facts<T>>>) -> Option<Facts<T>> {
+        let mut ans: Option<Facts<T>> = None;
+        for facts in list.into_iter().flatten() {
+            ans = Some(match ans {
+                None => facts,
+                Some(mut ans) => {
+                    ans.retain(|var, val| facts.get(var) == Some(val));
+                    ans
+                }
+            });
+        }
+        ans
+    }
+
+    /// Derives all facts entailed by the axioms.
+    fn close(&self, mut facts: Facts<T>) -> Option<Facts<T>> {
+        fn set<T: Eq + Hash + Clone>(facts: &mut Facts<T>, var: &T, val: bool, changed: &mut bool) -> Option<()> {
+            match facts.get(var) {
+                Some(&old) => (old == val).then_some(()),
+                None => {
+                    facts.insert(var.clone(), val);
+                    *changed = true;
+                    Some(())
+                }
+            }
+        }
+
+        loop {
+            let mut changed = false;
+
+            for group in self.at_most_one.iter().chain(&self.exactly_one) {
+                let mut trues = group.iter().filter(|v| facts.get(*v) == Some(&true));
+                if let Some(t) = trues.next() {
+                    if trues.next().is_some() {
+                        return None;
+                    }
+                    for v in group.iter().filter(|v| *v != t) {
+                        set(&mut facts, v, false, &mut changed)?;
+                    }
+                }
+            }
+
+            for group in &self.exactly_one {
+                let mut unknown = group.iter().filter(|v| facts.get(*v) != Some(&false));
+                match (unknown.next(), unknown.next()) {
+                    (None, _) => return None,
+                    (Some(v), None) => set(&mut facts, v, true, &mut changed)?,
+                    _ => {}
+                }
+            }
+
+            for (a, b) in &self.implications {
+                if facts.get(a) == Some(&true) {
+                    set(&mut facts, b, true, &mut changed)?;
+                }
+                if facts.get(b) == Some(&false) {
+                    set(&mut facts, a, false, &mut changed)?;
+                }
+            }
+
+            if !changed {
+                return Some(facts);
+            }
+        }
+    }
+}
+
+impl<T> Default for Axioms<T>
+where
+    T: Eq + Hash + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Uses each operand of `all` (`any`) as a true (false) context for its siblings,
+/// and replaces the variables decided by the context with constants.
+///
+/// `all(x0, any(x1, x2))` with `at_most_one(x0, x1)` => `all(x0, any(false, x2))`
+pub struct ImplyByAxioms<'a, T> {
+    axioms: &'a Axioms<T>,
+}
+
+impl<'a, T> ImplyByAxioms<'a, T> {
+    pub fn new(axioms: &'a Axioms<T>) -> Self {
+        Self { axioms }
+    }
+}
+
+impl<'a, T> ImplyByAxioms<'a, T>
+where
+    T: Eq + Hash + Clone,
+{
+    fn imply(&self, list: &mut [Expr<T>], positive: bool) -> bool {
+        let mut changed = false;
+        for i in 0..list.len() {
+            let Some(facts) = self.axioms.implied_with(&list[i], positive) else {
+                continue;
+            };
+            if facts.is_empty() {
+                continue;
+            }
+            for (_, x) in list.iter_mut().enumerate().filter(|&(j, _)| j != i) {
+                changed |= ReplaceFacts(&facts).visit_mut_changed(x);
+            }
+        }
+        changed
+    }
+
+    fn run(&self, expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        postorder_mut(expr, |expr| {
+            changed |= match expr {
+                Expr::Any(Any(any)) => self.imply(any, false),
+                Expr::All(All(all)) => self.imply(all, true),
+                _ => false,
+            }
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for ImplyByAxioms<'_, T>
+where
+    T: Eq + Hash + Clone,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        self.run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        self.run(expr)
+    }
+}
+
+struct ReplaceFacts<'a, T>(&'a Facts<T>);
+
+impl<T> ReplaceFacts<'_, T>
+where
+    T: Eq + Hash,
+{
+    fn run(&self, expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        preorder_mut(expr, |expr| {
+            if let Expr::Var(Var(var)) = expr {
+                if let Some(&val) = self.0.get(var) {
+                    *expr = Expr::Const(val);
+                    changed = true;
+                }
+            }
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for ReplaceFacts<'_, T>
+where
+    T: Eq + Hash,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        self.run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        self.run(expr)
+    }
+}
+
+/// Simplifies `expr` with the knowledge of `axioms` until it stops changing.
+pub fn simplify_with_axioms<T>(expr: &Expr<T>, axioms: &Axioms<T>) -> Expr<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut expr = expr.clone();
+    loop {
+        let mut changed = ImplyByAxioms::new(axioms).visit_mut_changed(&mut expr);
+        changed |= EvalConst.visit_mut_changed(&mut expr);
+        changed |= FlattenSingle.visit_mut_changed(&mut expr);
+        if !changed {
+            return expr;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+    use crate::eval::is_equivalent;
+
+    #[test]
+    fn simplify() {
+        let (x0, x1, x2, x3, x4) = (var(0), var(1), var(2), var(3), var(4));
+        let axioms = Axioms::new().exactly_one([0, 1, 2]).implies(3, 0);
+
+        let check = |src: Expr<u32>, expected: &str| {
+            let dst = simplify_with_axioms(&src, &axioms);
+            assert_eq!(dst.to_string(), expected);
+
+            let ctx = axioms.to_expr();
+            let lhs = expr(all((ctx.clone(), src)));
+            let rhs = expr(all((ctx, dst)));
+            assert!(is_equivalent(&lhs, &rhs).is_ok());
+        };
+
+        check(expr(all((any((x1, x2)), x0))), "false");
+        check(expr(all((x3, any((x0, x4))))), "3");
+        check(
+            expr(all((not(any((x1, x2))), any((x0, x4)), x4))),
+            "all(not(any(1, 2)), 4)",
+        );
+        check(expr(any((x0, all((not(x0), x1))))), "any(0, 1)");
+        check(expr(any((not(x1), all((x3, x2))))), "not(1)");
+    }
+}
@@ -0,0 +1,562 @@
+use crate::ast::{self, Expr, Var};
+use crate::visit::postorder_with;
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// A handle of a node in a [`Bdd`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Node(u32);
+
+impl Node {
+    pub const FALSE: Self = Self(0);
+    pub const TRUE: Self = Self(1);
+
+    pub fn is_const(self) -> bool {
+        self.0 < 2
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct NodeData {
+    var: u32,
+    low: Node,
+    high: Node,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Op {
+    And,
+    Or,
+}
+
+/// A manager of reduced ordered binary decision diagrams.
+///
+/// All nodes are stored in a shared table, so two nodes of the same manager are equal
+/// if and only if they represent the same boolean function.
+/// Variables are ordered by their first use.
+#[derive(Debug)]
+pub struct Bdd<T> {
+    vars: Vec<T>,
+    var_ids: HashMap<T, u32>,
+    nodes: Vec<NodeData>,
+    unique: HashMap<NodeData, Node>,
+    apply_cache: HashMap<(Op, Node, Node), Node>,
+    not_cache: HashMap<Node, Node>,
+}
+
+impl<T> Bdd<T>
+where
+    T: Eq + Hash + Clone,
+{
+    const TERMINAL: u32 = u32::MAX;
+
+    pub fn new() -> Self {
+        let terminal = |node| NodeData {
+            var: Self::TERMINAL,
+            low: node,
+            high: node,
+        };
+        Self {
+            vars: Vec::new(),
+            var_ids: HashMap::new(),
+            nodes: vec![terminal(Node::FALSE), terminal(Node::TRUE)],
+            unique: HashMap::new(),
+            apply_cache: HashMap::new(),
+            not_cache: HashMap::new(),
+        }
+    }
+
+    pub fn num_vars(&self) -> usize {
+        self.vars.len()
+    }
+
+    /// The number of nodes in the shared table, including the two terminals.
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn constant(&self, b: bool) -> Node {
+        if b {
+            Node::TRUE
+        } else {
+            Node::FALSE
+        }
+    }
+
+    pub fn var(&mut self, var: &T) -> Node {
+        let id = self.var_id(var);
+        self.mk(id, Node::FALSE, Node::TRUE)
+    }
+
+    fn var_id(&mut self, var: &T) -> u32 {
+        if let Some(&id) = self.var_ids.get(var) {
+            return id;
+        }
+        let id = self.vars.len() as u32;
+        self.vars.push(var.clone());
+        self.var_ids.insert(var.clone(), id);
+        id
+    }
+
+    fn level(&self, f: Node) -> u32 {
+        self.nodes[f.0 as usize].var
+    }
+
+    fn mk(&mut self, var: u32, low: Node, high: Node) -> Node {
+        if low == high {
+            return low;
+        }
+        let data = NodeData { var, low, high };
+        if let Some(&node) = self.unique.get(&data) {
+            return node;
+        }
+        let node = Node(self.nodes.len() as u32);
+        self.nodes.push(data);
+        self.unique.insert(data, node);
+        node
+    }
+
+    fn cofactors(&self, f: Node, var: u32) -> (Node, Node) {
+        let data = self.nodes[f.0 as usize];
+        if data.var == var {
+            (data.low, data.high)
+        } else {
+            (f, f)
+        }
+    }
+
+    pub fn not(&mut self, f: Node) -> Node {
+        match f {
+            Node::FALSE => return Node::TRUE,
+            Node::TRUE => return Node::FALSE,
+            _ => {}
+        }
+        if let Some(&r) = self.not_cache.get(&f) {
+            return r;
+        }
+        let data = self.nodes[f.0 as usize];
+        let low = self.not(data.low);
+        let high = self.not(data.high);
+        let r = self.mk(data.var, low, high);
+        self.not_cache.insert(f, r);
+        r
+    }
+
+    pub fn and(&mut self, f: Node, g: Node) -> Node {
+        self.apply(Op::And, f, g)
+    }
+
+    pub fn or(&mut self, f: Node, g: Node) -> Node {
+        self.apply(Op::Or, f, g)
+    }
+
+    fn apply(&mut self, op: Op, f: Node, g: Node) -> Node {
+        let (zero, one) = match op {
+            Op::And => (Node::FALSE, Node::TRUE),
+            Op::Or => (Node::TRUE, Node::FALSE),
+        };
+        if f == zero || g == zero {
+            return zero;
+        }
+        if f == one || f == g {
+            return g;
+        }
+        if g == one {
+            return f;
+        }
+
+        let (f, g) = if f < g { (f, g) } else { (g, f) };
+        if let Some(&r) = self.apply_cache.get(&(op, f, g)) {
+            return r;
+        }
+
+        let var = self.level(f).min(self.level(g));
+        let (f0, f1) = self.cofactors(f, var);
+        let (g0, g1) = self.cofactors(g, var);
+        let low = self.apply(op, f0, g0);
+        let high = self.apply(op, f1, g1);
+        let r = self.mk(var, low, high);
+
+        self.apply_cache.insert((op, f, g), r);
+        r
+    }
+
+    /// Substitutes `val` for `var`.
+    pub fn restrict(&mut self, f: Node, var: &T, val: bool) -> Node {
+        match self.var_ids.get(var) {
+            Some(&id) => self.restrict_id(f, id, val, &mut HashMap::new()),
+            None => f,
+        }
+    }
+
+    fn restrict_id(&mut self, f: Node, var: u32, val: bool, memo: &mut HashMap<Node, Node>) -> Node {
+        let data = self.nodes[f.0 as usize];
+        if data.var == Self::TERMINAL || data.var > var {
+            return f;
+        }
+        if data.var == var {
+            return if val { data.high } else { data.low };
+        }
+        if let Some(&r) = memo.get(&f) {
+            return r;
+        }
+        let low = self.restrict_id(data.low, var, val, memo);
+        let high = self.restrict_id(data.high, var, val, memo);
+        let r = self.mk(data.var, low, high);
+        memo.insert(f, r);
+        r
+    }
+
+    /// Existential quantification: `exists vars. f`
+    pub fn exists(&mut self, f: Node, vars: &[T]) -> Node {
+        vars.iter().fold(f, |f, var| {
+            let low = self.restrict(f, var, false);
+            let high = self.restrict(f, var, true);
+            self.or(low, high)
+        })
+    }
+
+    /// Universal quantification: `forall vars. f`
+    pub fn forall(&mut self, f: Node, vars: &[T]) -> Node {
+        vars.iter().fold(f, |f, var| {
+            let low = self.restrict(f, var, false);
+            let high = self.restrict(f, var, true);
+            self.and(low, high)
+        })
+    }
+
+    pub fn from_expr(&mut self, expr: &Expr<T>) -> Node {
+        postorder_with(expr, |expr, operands| match expr {
+            Expr::Any(_) => operands.into_iter().fold(Node::FALSE, |acc, f| self.or(acc, f)),
+            Expr::All(_) => operands.into_iter().fold(Node::TRUE, |acc, f| self.and(acc, f)),
+            Expr::Not(_) => self.not(operands[0]),
+            Expr::Var(Var(var)) => self.var(var),
+            Expr::Const(b) => self.constant(*b),
+        })
+    }
+
+    /// Converts `f` back into an expression by Shannon expansion along the variable order.
+    pub fn to_expr(&self, f: Node) -> Expr<T> {
+        // the expansions of the nodes below `f`, built bottom-up without recursion
+        let mut memo: HashMap<Node, Expr<T>> = HashMap::new();
+        let mut stack = vec![f];
+        while let Some(&g) = stack.last() {
+            if g.is_const() || memo.contains_key(&g) {
+                stack.pop();
+                continue;
+            }
+            let NodeData { low, high, .. } = self.nodes[g.0 as usize];
+            let pending = [low, high]
+                .into_iter()
+                .filter(|h| !h.is_const() && !memo.contains_key(h));
+            let len = stack.len();
+            stack.extend(pending);
+            if stack.len() == len {
+                stack.pop();
+                let expr = self.expand(g, |h| memo[&h].clone());
+                memo.insert(g, expr);
+            }
+        }
+        match f {
+            Node::FALSE => Expr::Const(false),
+            Node::TRUE => Expr::Const(true),
+            _ => memo.remove(&f).unwrap(),
+        }
+    }
+
+    /// Expands a non-constant node `f`, given the expansions of its non-constant children.
+    fn expand(&self, f: Node, child: impl Fn(Node) -> Expr<T>) -> Expr<T> {
+        let NodeData { var, low, high } = self.nodes[f.0 as usize];
+        let x = || ast::expr(ast::var(self.vars[var as usize].clone()));
+        let not_x = || ast::expr(ast::not(x()));
+
+        match (low, high) {
+            (Node::FALSE, Node::TRUE) => x(),
+            (Node::TRUE, Node::FALSE) => not_x(),
+            (Node::FALSE, _) => conj(x(), child(high)),
+            (_, Node::FALSE) => conj(not_x(), child(low)),
+            (_, Node::TRUE) => disj(x(), child(low)),
+            (Node::TRUE, _) => disj(not_x(), child(high)),
+            _ => disj(conj(x(), child(high)), conj(not_x(), child(low))),
+        }
+    }
+
+    pub fn eval<F>(&self, mut f: Node, assign: &F) -> bool
+    where
+        F: for<'v> Fn(&'v T) -> bool,
+    {
+        while !f.is_const() {
+            let data = self.nodes[f.0 as usize];
+            f = if assign(&self.vars[data.var as usize]) {
+                data.high
+            } else {
+                data.low
+            };
+        }
+        f == Node::TRUE
+    }
+
+    /// Counts the satisfying assignments of `f` over all variables of the manager.
+    ///
+    /// Returns `None` if the count does not fit in `u128`,
+    /// which can only happen with 128 or more variables.
+    pub fn sat_count(&self, f: Node) -> Option<u128> {
+        self.sat_count_upto(f, self.vars.len())
+    }
+
+    /// Counts the satisfying assignments of `f` over the first `end` variables of the manager.
+    ///
+    /// `f` must not depend on the other variables.
+    pub(crate) fn sat_count_upto(&self, f: Node, end: usize) -> Option<u128> {
+        let end = end as u32;
+        let count = self.sat_count_rec(f, end, &mut HashMap::new())?;
+        shl(count, self.level_or_end(f, end))
+    }
+
+    /// Counts the satisfying assignments of the variables from `level(f)` to `end`.
+    fn sat_count_rec(&self, f: Node, end: u32, memo: &mut HashMap<Node, u128>) -> Option<u128> {
+        match f {
+            Node::FALSE => return Some(0),
+            Node::TRUE => return Some(1),
+            _ => {}
+        }
+        if let Some(&c) = memo.get(&f) {
+            return Some(c);
+        }
+        let NodeData { var, low, high } = self.nodes[f.0 as usize];
+        debug_assert!(var < end, "depends on a variable after the end");
+        let low_count = shl(
+            self.sat_count_rec(low, end, memo)?,
+            self.level_or_end(low, end) - var - 1,
+        )?;
+        let high_count = shl(
+            self.sat_count_rec(high, end, memo)?,
+            self.level_or_end(high, end) - var - 1,
+        )?;
+        let c = low_count.checked_add(high_count)?;
+        memo.insert(f, c);
+        Some(c)
+    }
+
+    /// Returns the probability that `f` is true
+    /// if each variable is independently true with probability `p(var)`.
+    pub fn probability<F>(&self, f: Node, p: &F) -> f64
+    where
+        F: for<'v> Fn(&'v T) -> f64,
+    {
+        self.probability_rec(f, p, &mut HashMap::new())
+    }
+
+    fn probability_rec<F>(&self, f: Node, p: &F, memo: &mut HashMap<Node, f64>) -> f64
+    where
+        F: for<'v> Fn(&'v T) -> f64,
+    {
+        match f {
+            Node::FALSE => return 0.0,
+            Node::TRUE => return 1.0,
+            _ => {}
+        }
+        if let Some(&prob) = memo.get(&f) {
+            return prob;
+        }
+        let NodeData { var, low, high } = self.nodes[f.0 as usize];
+        let px = p(&self.vars[var as usize]);
+        let prob = (1.0 - px) * self.probability_rec(low, p, memo) + px * self.probability_rec(high, p, memo);
+        memo.insert(f, prob);
+        prob
+    }
+
+    fn level_or_end(&self, f: Node, end: u32) -> u32 {
+        if f.is_const() {
+            end
+        } else {
+            self.level(f)
+        }
+    }
+
+    /// Returns the paths from `f` to the true terminal as disjoint cubes.
+    pub fn cubes(&self, f: Node) -> Vec<Vec<(&T, bool)>> {
+        let mut ans = Vec::new();
+        let mut path = Vec::new();
+        self.cubes_rec(f, &mut path, &mut ans);
+        ans
+    }
+
+    fn cubes_rec<'a>(&'a self, f: Node, path: &mut Vec<(&'a T, bool)>, ans: &mut Vec<Vec<(&'a T, bool)>>) {
+        match f {
+            Node::FALSE => return,
+            Node::TRUE => return ans.push(path.clone()),
+            _ => {}
+        }
+        let NodeData { var, low, high } = self.nodes[f.0 as usize];
+        let x = &self.vars[var as usize];
+        for (child, val) in [(high, true), (low, false)] {
+            path.push((x, val));
+            self.cubes_rec(child, path, ans);
+            path.pop();
+        }
+    }
+
+    /// The number of nodes reachable from `f`, including terminals.
+    pub fn size(&self, f: Node) -> usize {
+        let mut visited = HashSet::from([f]);
+        let mut stack = vec![f];
+        while let Some(f) = stack.pop() {
+            if f.is_const() {
+                continue;
+            }
+            let data = self.nodes[f.0 as usize];
+            for child in [data.low, data.high] {
+                if visited.insert(child) {
+                    stack.push(child);
+                }
+            }
+        }
+        visited.len()
+    }
+}
+
+/// Returns `count * 2^n`, or `None` on overflow.
+fn shl(count: u128, n: u32) -> Option<u128> {
+    if count == 0 {
+        Some(0)
+    } else if n <= count.leading_zeros() {
+        Some(count << n)
+    } else {
+        None
+    }
+}
+
+impl<T> Default for Bdd<T>
+where
+    T: Eq + Hash + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn conj<T>(lhs: Expr<T>, rhs: Expr<T>) -> Expr<T> {
+    match rhs {
+        Expr::All(all) => {
+            let mut list = all.into_inner();
+            list.insert(0, lhs);
+            ast::expr(ast::all(list))
+        }
+        rhs => ast::expr(ast::all((lhs, rhs))),
+    }
+}
+
+fn disj<T>(lhs: Expr<T>, rhs: Expr<T>) -> Expr<T> {
+    match rhs {
+        Expr::Any(any) => {
+            let mut list = any.into_inner();
+            list.insert(0, lhs);
+            ast::expr(ast::any(list))
+        }
+        rhs => ast::expr(ast::any((lhs, rhs))),
+    }
+}
+
+/// Simplifies `expr` by converting it into a BDD and back.
+pub fn simplify<T>(expr: &Expr<T>) -> Expr<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut bdd = Bdd::new();
+    let f = bdd.from_expr(expr);
+    bdd.to_expr(f)
+}
+
+/// Returns an expression without `vars` which is true if `expr` is true for some values of `vars`.
+pub fn exists<T>(expr: &Expr<T>, vars: &[T]) -> Expr<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut bdd = Bdd::new();
+    let f = bdd.from_expr(expr);
+    let f = bdd.exists(f, vars);
+    bdd.to_expr(f)
+}
+
+/// Returns an expression without `vars` which is true if `expr` is true for all values of `vars`.
+pub fn forall<T>(expr: &Expr<T>, vars: &[T]) -> Expr<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut bdd = Bdd::new();
+    let f = bdd.from_expr(expr);
+    let f = bdd.forall(f, vars);
+    bdd.to_expr(f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+    use crate::eval::is_equivalent;
+
+    #[test]
+    fn canonical() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        let mut bdd = Bdd::new();
+        let f = bdd.from_expr(&expr(any((all((x0, x1)), all((x0, not(x1)))))));
+        let g = bdd.from_expr(&expr(x0));
+        assert_eq!(f, g);
+
+        let f = bdd.from_expr(&expr(not(all((x0, x2)))));
+        let g = bdd.from_expr(&expr(any((not(x2), not(x0)))));
+        assert_eq!(f, g);
+        assert_eq!(bdd.sat_count(f), Some(6));
+        assert_eq!(bdd.size(f), 4);
+
+        let f = bdd.exists(f, &[0]);
+        assert_eq!(f, Node::TRUE);
+    }
+
+    #[test]
+    fn sat_count() {
+        let mut bdd = Bdd::new();
+        let vars: Vec<Node> = (0..128).map(|i| bdd.var(&i)).collect();
+        assert_eq!(bdd.sat_count(Node::FALSE), Some(0));
+        assert_eq!(bdd.sat_count(Node::TRUE), None);
+        assert_eq!(bdd.sat_count(vars[0]), Some(1 << 127));
+
+        let f = bdd.or(vars[0], vars[127]);
+        assert_eq!(bdd.sat_count(f), Some(3 << 126));
+        let f = bdd.and(vars[0], vars[127]);
+        assert_eq!(bdd.sat_count(f), Some(1 << 126));
+        assert_eq!(bdd.size(f), 4);
+    }
+
+    #[test]
+    fn to_expr() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        let src = expr(all((not(any((x0, x1))), any((x2, x0)), const_(true))));
+        let dst = simplify(&src);
+        assert_eq!(dst.to_string(), "all(not(0), not(1), 2)");
+        assert!(is_equivalent(&src, &dst).is_ok());
+
+        let mut bdd = Bdd::new();
+        let f = bdd.from_expr(&src);
+        let f = bdd.restrict(f, &2, true);
+        assert_eq!(bdd.to_expr(f).to_string(), "all(not(0), not(1))");
+        assert!(bdd.eval(f, &|_| false));
+    }
+
+    #[test]
+    fn quantify() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        // available on `0` with `1`, or on `2` without `1`
+        let src = expr(any((all((x0, x1)), all((x2, not(x1))))));
+        assert_eq!(exists(&src, &[1]).to_string(), "any(0, 2)");
+        assert_eq!(forall(&src, &[1]).to_string(), "all(0, 2)");
+        assert_eq!(exists(&src, &[0, 1, 2]).to_string(), "true");
+        assert_eq!(forall(&src, &[3]), simplify(&src));
+    }
+}
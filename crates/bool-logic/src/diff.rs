@@ -0,0 +1,216 @@
+use crate::ast::{All, Any, Expr, Not};
+use crate::bdd::Bdd;
+
+use std::fmt;
+use std::hash::Hash;
+
+/// How the set of satisfying assignments changed between two expressions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SemanticDiff<T> {
+    /// True where the old expression is true and the new one is false.
+    pub only_old: Expr<T>,
+    /// True where the new expression is true and the old one is false.
+    pub only_new: Expr<T>,
+    /// True where both expressions are true.
+    pub both: Expr<T>,
+}
+
+impl<T> SemanticDiff<T> {
+    /// Returns `true` if the two expressions are equivalent.
+    pub fn is_unchanged(&self) -> bool {
+        self.only_old.is_const_false() && self.only_new.is_const_false()
+    }
+}
+
+pub fn semantic_diff<T>(old: &Expr<T>, new: &Expr<T>) -> SemanticDiff<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut bdd = Bdd::new();
+    let f = bdd.from_expr(old);
+    let g = bdd.from_expr(new);
+
+    let not_f = bdd.not(f);
+    let not_g = bdd.not(g);
+    let only_old = bdd.and(f, not_g);
+    let only_new = bdd.and(g, not_f);
+    let both = bdd.and(f, g);
+
+    SemanticDiff {
+        only_old: bdd.to_expr(only_old),
+        only_new: bdd.to_expr(only_new),
+        both: bdd.to_expr(both),
+    }
+}
+
+/// A structural diff of two expression trees.
+///
+/// The operands of `any` and `all` are compared as multisets.
+/// `Removed` and `Added` only occur as operands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeDiff<T> {
+    Same(Expr<T>),
+    Removed(Expr<T>),
+    Added(Expr<T>),
+    Replaced { old: Expr<T>, new: Expr<T> },
+    Not(Box<TreeDiff<T>>),
+    Any(Vec<TreeDiff<T>>),
+    All(Vec<TreeDiff<T>>),
+}
+
+pub fn tree_diff<T>(old: &Expr<T>, new: &Expr<T>) -> TreeDiff<T>
+where
+    T: Eq + Clone,
+{
+    if old == new {
+        return TreeDiff::Same(old.clone());
+    }
+    match (old, new) {
+        (Expr::Any(Any(lhs)), Expr::Any(Any(rhs))) => TreeDiff::Any(list_diff(lhs, rhs)),
+        (Expr::All(All(lhs)), Expr::All(All(rhs))) => TreeDiff::All(list_diff(lhs, rhs)),
+        (Expr::Not(Not(lhs)), Expr::Not(Not(rhs))) => TreeDiff::Not(Box::new(tree_diff(lhs, rhs))),
+        _ => TreeDiff::Replaced {
+            old: old.clone(),
+            new: new.clone(),
+        },
+    }
+}
+
+fn is_same_kind<T>(lhs: &Expr<T>, rhs: &Expr<T>) -> bool {
+    matches!(
+        (lhs, rhs),
+        (Expr::Any(_), Expr::Any(_)) | (Expr::All(_), Expr::All(_)) | (Expr::Not(_), Expr::Not(_))
+    )
+}
+
+/// Keeps equal operands, pairs up the remaining operands of the same kind, and reports the rest.
+fn list_diff<T>(old: &[Expr<T>], new: &[Expr<T>]) -> Vec<TreeDiff<T>>
+where
+    T: Eq + Clone,
+{
+    let mut used = vec![false; new.len()];
+    let mut matched: Vec<Option<usize>> = old
+        .iter()
+        .map(|x| {
+            let j = (0..new.len()).find(|&j| !used[j] && new[j] == *x)?;
+            used[j] = true;
+            Some(j)
+        })
+        .collect();
+
+    for (i, x) in old.iter().enumerate() {
+        if matched[i].is_some() {
+            continue;
+        }
+        if let Some(j) = (0..new.len()).find(|&j| !used[j] && is_same_kind(x, &new[j])) {
+            used[j] = true;
+            matched[i] = Some(j);
+        }
+    }
+
+    let mut ans: Vec<TreeDiff<T>> = old
+        .iter()
+        .zip(&matched)
+        .map(|(x, j)| match j {
+            Some(j) => tree_diff(x, &new[*j]),
+            None => TreeDiff::Removed(x.clone()),
+        })
+        .collect();
+    ans.extend(
+        new.iter()
+            .zip(&used)
+            .filter(|(_, &u)| !u)
+            .map(|(x, _)| TreeDiff::Added(x.clone())),
+    );
+    ans
+}
+
+/// Renders the diff line by line, prefixing removed lines with `-` and added lines with `+`.
+impl<T> fmt::Display for TreeDiff<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.render(f, 0, "")
+    }
+}
+
+impl<T> TreeDiff<T>
+where
+    T: fmt::Display,
+{
+    fn render(&self, f: &mut fmt::Formatter<'_>, depth: usize, sep: &str) -> fmt::Result {
+        let indent = "    ".repeat(depth);
+        match self {
+            TreeDiff::Same(x) => writeln!(f, "  {indent}{x}{sep}"),
+            TreeDiff::Removed(x) => writeln!(f, "- {indent}{x}{sep}"),
+            TreeDiff::Added(x) => writeln!(f, "+ {indent}{x}{sep}"),
+            TreeDiff::Replaced { old, new } => {
+                writeln!(f, "- {indent}{old}{sep}")?;
+                writeln!(f, "+ {indent}{new}{sep}")
+            }
+            TreeDiff::Not(not) => {
+                writeln!(f, "  {indent}not(")?;
+                not.render(f, depth + 1, "")?;
+                writeln!(f, "  {indent}){sep}")
+            }
+            TreeDiff::Any(list) | TreeDiff::All(list) => {
+                let op = if matches!(self, TreeDiff::Any(_)) { "any" } else { "all" };
+                writeln!(f, "  {indent}{op}(")?;
+                for x in list {
+                    x.render(f, depth + 1, ",")?;
+                }
+                writeln!(f, "  {indent}){sep}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    #[test]
+    fn semantic() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+        let old = expr(any((x0, x1)));
+        let new = expr(any((x0, x2)));
+
+        let diff = semantic_diff(&old, &new);
+        assert_eq!(diff.only_old.to_string(), "all(not(0), 1, not(2))");
+        assert_eq!(diff.only_new.to_string(), "all(not(0), not(1), 2)");
+        assert_eq!(diff.both.to_string(), "any(0, all(1, 2))");
+        assert!(!diff.is_unchanged());
+
+        let new = expr(any((x1, x0)));
+        assert!(semantic_diff(&old, &new).is_unchanged());
+    }
+
+    #[test]
+    fn tree() {
+        let (x0, x1, x2, x3) = (var(0), var(1), var(2), var(3));
+        let old = expr(any((x0, all((x1, x2)), not(x3))));
+        let new = expr(any((all((x1, x3)), x0, x2)));
+
+        let diff = tree_diff(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            [
+                "  any(",
+                "      0,",
+                "      all(",
+                "          1,",
+                "-         2,",
+                "+         3,",
+                "      ),",
+                "-     not(3),",
+                "+     2,",
+                "  )",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
@@ -0,0 +1,747 @@
+use crate::ast::{self, Expr, Var};
+use crate::visit::postorder_with;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::hash::Hash;
+use std::slice;
+
+/// A handle of an e-class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Id(u32);
+
+/// An e-node whose children are e-classes.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ENode<T> {
+    Any(Vec<Id>),
+    All(Vec<Id>),
+    Not(Id),
+    Var(T),
+    Const(bool),
+}
+
+impl<T> ENode<T> {
+    pub fn children(&self) -> &[Id] {
+        match self {
+            ENode::Any(list) | ENode::All(list) => list,
+            ENode::Not(not) => slice::from_ref(not),
+            ENode::Var(_) | ENode::Const(_) => &[],
+        }
+    }
+}
+
+/// The rewrite rules applied by [`Optimizer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// `any(x0, all(x0, x1)) => x0`, `any(x0, all(not(x0), x1)) => any(x0, x1)`
+    Absorption,
+    /// `not(any(x0, x1)) <=> all(not(x0), not(x1))`
+    DeMorgan,
+    /// `all(x0, any(x1, x2)) <=> any(all(x0, x1), all(x0, x2))`
+    Distribution,
+    /// `any(all(x0, x1), all(not(x0), x2), all(x1, x2)) => any(all(x0, x1), all(not(x0), x2))`
+    Consensus,
+    /// `any(x0, x0) => any(x0)`, `any(x0) => x0`
+    Idempotence,
+    /// `any(x0, any(x1, x2)) => any(x0, x1, x2)`
+    Associativity,
+    /// `any(x0, not(x0)) => true`
+    Complement,
+    /// `any(x0, true) => true`, `any(x0, false) => any(x0)`
+    Constant,
+    /// `not(not(x0)) => x0`
+    DoubleNegation,
+}
+
+impl Rule {
+    pub const ALL: &'static [Rule] = &[
+        Rule::Absorption,
+        Rule::DeMorgan,
+        Rule::Distribution,
+        Rule::Consensus,
+        Rule::Idempotence,
+        Rule::Associativity,
+        Rule::Complement,
+        Rule::Constant,
+        Rule::DoubleNegation,
+    ];
+}
+
+/// A term to be added to the e-graph by a rewrite.
+enum Term {
+    Class(Id),
+    List(bool, Vec<Term>),
+    Not(Box<Term>),
+    Const(bool),
+}
+
+impl Term {
+    fn not(term: Term) -> Self {
+        Term::Not(Box::new(term))
+    }
+
+    fn list(is_any: bool, list: impl IntoIterator<Item = Id>) -> Self {
+        Term::List(is_any, list.into_iter().map(Term::Class).collect())
+    }
+}
+
+/// The rewrites found by matching, up to a limit.
+struct Matches {
+    list: Vec<(Id, Term)>,
+    limit: usize,
+}
+
+impl Matches {
+    fn push(&mut self, action: (Id, Term)) {
+        if !self.is_full() {
+            self.list.push(action);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.list.len() >= self.limit
+    }
+}
+
+/// An e-graph of boolean expressions.
+///
+/// The operands of `any` and `all` are kept sorted, so commutativity is built in.
+#[derive(Debug)]
+pub struct EGraph<T> {
+    parents: Vec<Id>,
+    classes: BTreeMap<Id, Vec<ENode<T>>>,
+    memo: HashMap<ENode<T>, Id>,
+}
+
+impl<T> EGraph<T>
+where
+    T: Eq + Hash + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            parents: Vec::new(),
+            classes: BTreeMap::new(),
+            memo: HashMap::new(),
+        }
+    }
+
+    pub fn num_classes(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.memo.len()
+    }
+
+    pub fn find(&self, mut id: Id) -> Id {
+        while self.parents[id.0 as usize] != id {
+            id = self.parents[id.0 as usize];
+        }
+        id
+    }
+
+    /// Returns the nodes of the class of `id`.
+    pub fn nodes(&self, id: Id) -> &[ENode<T>] {
+        &self.classes[&self.find(id)]
+    }
+
+    fn canonicalize(&self, node: ENode<T>) -> ENode<T> {
+        let canonical_list = |list: Vec<Id>| {
+            let mut list: Vec<Id> = list.into_iter().map(|id| self.find(id)).collect();
+            list.sort_unstable();
+            list
+        };
+        match node {
+            ENode::Any(list) => ENode::Any(canonical_list(list)),
+            ENode::All(list) => ENode::All(canonical_list(list)),
+            ENode::Not(not) => ENode::Not(self.find(not)),
+            node => node,
+        }
+    }
+
+    /// Returns the class of `node`, adding it to the e-graph if it is new.
+    pub fn add(&mut self, node: ENode<T>) -> Id {
+        let node = self.canonicalize(node);
+        if let Some(&id) = self.memo.get(&node) {
+            return self.find(id);
+        }
+        let id = Id(self.parents.len() as u32);
+        self.parents.push(id);
+        self.classes.insert(id, vec![node.clone()]);
+        self.memo.insert(node, id);
+        id
+    }
+
+    pub fn add_expr(&mut self, expr: &Expr<T>) -> Id {
+        postorder_with(expr, |expr, ids| {
+            let node = match expr {
+                Expr::Any(_) => ENode::Any(ids),
+                Expr::All(_) => ENode::All(ids),
+                Expr::Not(_) => ENode::Not(ids[0]),
+                Expr::Var(Var(var)) => ENode::Var(var.clone()),
+                Expr::Const(b) => ENode::Const(*b),
+            };
+            self.add(node)
+        })
+    }
+
+    fn add_term(&mut self, term: Term) -> Id {
+        match term {
+            Term::Class(id) => id,
+            Term::List(is_any, list) => {
+                let list = list.into_iter().map(|t| self.add_term(t)).collect();
+                self.add(if is_any { ENode::Any(list) } else { ENode::All(list) })
+            }
+            Term::Not(not) => {
+                let not = self.add_term(*not);
+                self.add(ENode::Not(not))
+            }
+            Term::Const(b) => self.add(ENode::Const(b)),
+        }
+    }
+
+    /// Merges two classes. Returns `false` if they are already equal.
+    ///
+    /// [`EGraph::rebuild`] must be called before the next query.
+    pub fn union(&mut self, lhs: Id, rhs: Id) -> bool {
+        let (lhs, rhs) = (self.find(lhs), self.find(rhs));
+        if lhs == rhs {
+            return false;
+        }
+        let (root, child) = if lhs < rhs { (lhs, rhs) } else { (rhs, lhs) };
+        self.parents[child.0 as usize] = root;
+        let nodes = self.classes.remove(&child).unwrap();
+        self.classes.get_mut(&root).unwrap().extend(nodes);
+        true
+    }
+
+    /// Restores the congruence invariant after unions.
+    pub fn rebuild(&mut self) {
+        loop {
+            let mut pending = Vec::new();
+            self.memo.clear();
+
+            let roots: Vec<Id> = self.classes.keys().copied().collect();
+            for root in roots {
+                let nodes = self.classes.remove(&root).unwrap();
+                let mut seen = HashSet::new();
+                let mut list = Vec::with_capacity(nodes.len());
+                for node in nodes {
+                    let node = self.canonicalize(node);
+                    if seen.insert(node.clone()) {
+                        list.push(node);
+                    }
+                }
+                for node in &list {
+                    match self.memo.get(node) {
+                        Some(&other) if other != root => pending.push((other, root)),
+                        Some(_) => {}
+                        None => {
+                            self.memo.insert(node.clone(), root);
+                        }
+                    }
+                }
+                self.classes.insert(root, list);
+            }
+
+            if pending.is_empty() {
+                break;
+            }
+            for (lhs, rhs) in pending {
+                self.union(lhs, rhs);
+            }
+        }
+    }
+
+    fn lookup(&self, node: ENode<T>) -> Option<Id> {
+        let node = self.canonicalize(node);
+        self.memo.get(&node).map(|&id| self.find(id))
+    }
+
+    fn has_const(&self, id: Id, val: bool) -> bool {
+        self.nodes(id).contains(&ENode::Const(val))
+    }
+
+    /// Returns `x` if the class of `id` contains `not(x)`.
+    fn negated(&self, id: Id) -> Option<Id> {
+        self.nodes(id).iter().find_map(|node| match node {
+            ENode::Not(not) => Some(*not),
+            _ => None,
+        })
+    }
+
+    fn is_negation(&self, lhs: Id, rhs: Id) -> bool {
+        self.negated(lhs) == Some(rhs) || self.negated(rhs) == Some(lhs)
+    }
+
+    /// Returns the operand lists of the `any` (`all`) nodes in the class of `id`.
+    fn lists(&self, id: Id, is_any: bool) -> impl Iterator<Item = &[Id]> {
+        self.nodes(id).iter().filter_map(move |node| match node {
+            ENode::Any(list) if is_any => Some(list.as_slice()),
+            ENode::All(list) if !is_any => Some(list.as_slice()),
+            _ => None,
+        })
+    }
+
+    /// Applies the rules once, stopping early when the e-graph has more than `node_limit` nodes.
+    /// Returns `false` if the e-graph is saturated.
+    pub fn apply(&mut self, rules: &[Rule], node_limit: usize) -> bool {
+        let mut matches = Matches {
+            list: Vec::new(),
+            limit: node_limit,
+        };
+        'outer: for (&id, nodes) in &self.classes {
+            for node in nodes {
+                for &rule in rules {
+                    if matches.is_full() {
+                        break 'outer;
+                    }
+                    self.match_rule(rule, id, node, &mut matches);
+                }
+            }
+        }
+
+        let mut changed = false;
+        for (id, term) in matches.list {
+            if self.num_nodes() > node_limit {
+                break;
+            }
+            let new = self.add_term(term);
+            changed |= self.union(id, new);
+        }
+        self.rebuild();
+        changed
+    }
+
+    fn match_rule(&self, rule: Rule, id: Id, node: &ENode<T>, out: &mut Matches) {
+        match node {
+            ENode::Any(list) => self.match_list(rule, id, list, true, out),
+            ENode::All(list) => self.match_list(rule, id, list, false, out),
+            ENode::Not(not) => match rule {
+                Rule::Constant => {
+                    for val in [true, false] {
+                        if self.has_const(*not, val) {
+                            out.push((id, Term::Const(!val)));
+                        }
+                    }
+                }
+                Rule::DoubleNegation => {
+                    if let Some(x) = self.negated(*not) {
+                        out.push((id, Term::Class(x)));
+                    }
+                }
+                Rule::DeMorgan => {
+                    for is_any in [true, false] {
+                        for inner in self.lists(*not, is_any) {
+                            let list = inner.iter().map(|&x| Term::not(Term::Class(x))).collect();
+                            out.push((id, Term::List(!is_any, list)));
+                        }
+                    }
+                }
+                _ => {}
+            },
+            ENode::Var(_) | ENode::Const(_) => {}
+        }
+    }
+
+    /// Matches `any(list)` if `is_any`, otherwise `all(list)`.
+    fn match_list(&self, rule: Rule, id: Id, list: &[Id], is_any: bool, out: &mut Matches) {
+        let without = |skip: &[usize]| {
+            list.iter()
+                .enumerate()
+                .filter(|(i, _)| !skip.contains(i))
+                .map(|(_, &x)| x)
+                .collect::<Vec<Id>>()
+        };
+
+        match rule {
+            Rule::Constant => {
+                if list.iter().any(|&x| self.has_const(x, is_any)) {
+                    out.push((id, Term::Const(is_any)));
+                } else if list.iter().any(|&x| self.has_const(x, !is_any)) {
+                    let rest = list.iter().copied().filter(|&x| !self.has_const(x, !is_any));
+                    out.push((id, Term::list(is_any, rest)));
+                } else if list.is_empty() {
+                    out.push((id, Term::Const(!is_any)));
+                }
+            }
+            Rule::Complement => {
+                if list.iter().any(|&x| list.iter().any(|&y| self.negated(y) == Some(x))) {
+                    out.push((id, Term::Const(is_any)));
+                }
+            }
+            Rule::Idempotence => {
+                if let [x] = list {
+                    out.push((id, Term::Class(*x)));
+                } else if list.windows(2).any(|w| w[0] == w[1]) {
+                    let mut rest = list.to_vec();
+                    rest.dedup();
+                    out.push((id, Term::list(is_any, rest)));
+                }
+            }
+            Rule::Associativity => {
+                for (i, &x) in list.iter().enumerate() {
+                    for inner in self.lists(x, is_any) {
+                        let mut rest = without(&[i]);
+                        rest.extend_from_slice(inner);
+                        out.push((id, Term::list(is_any, rest)));
+                    }
+                }
+            }
+            Rule::Absorption => {
+                for (i, &x) in list.iter().enumerate() {
+                    for inner in self.lists(x, !is_any) {
+                        let others = without(&[i]);
+                        if inner.iter().any(|y| others.contains(y)) {
+                            out.push((id, Term::list(is_any, others)));
+                            continue;
+                        }
+                        let kept: Vec<Id> = inner
+                            .iter()
+                            .copied()
+                            .filter(|&y| !others.iter().any(|&z| self.is_negation(y, z)))
+                            .collect();
+                        if kept.len() < inner.len() {
+                            let mut rest: Vec<Term> = others.into_iter().map(Term::Class).collect();
+                            rest.push(Term::list(!is_any, kept));
+                            out.push((id, Term::List(is_any, rest)));
+                        }
+                    }
+                }
+            }
+            Rule::DeMorgan => {
+                let negated: Option<Vec<Id>> = list.iter().map(|&x| self.negated(x)).collect();
+                if let Some(inner) = negated {
+                    out.push((id, Term::not(Term::list(!is_any, inner))));
+                }
+            }
+            Rule::Distribution => {
+                for (i, &x) in list.iter().enumerate() {
+                    for inner in self.lists(x, !is_any) {
+                        let rest = without(&[i]);
+                        if rest.is_empty() {
+                            continue;
+                        }
+                        let dist = inner.iter().map(|&y| {
+                            let mut conj = rest.clone();
+                            conj.push(y);
+                            Term::list(is_any, conj)
+                        });
+                        out.push((id, Term::List(!is_any, dist.collect())));
+                    }
+                }
+                self.match_pairs(list, is_any, out, |out, i, j, lhs, rhs| {
+                    let common: Vec<Id> = lhs.iter().copied().filter(|x| rhs.contains(x)).collect();
+                    if common.is_empty() {
+                        return;
+                    }
+                    let lhs = lhs.iter().copied().filter(|x| !common.contains(x));
+                    let rhs = rhs.iter().copied().filter(|x| !common.contains(x));
+                    let pair = Term::List(is_any, vec![Term::list(!is_any, lhs), Term::list(!is_any, rhs)]);
+
+                    let mut factor: Vec<Term> = common.into_iter().map(Term::Class).collect();
+                    factor.push(pair);
+                    let mut rest: Vec<Term> = without(&[i, j]).into_iter().map(Term::Class).collect();
+                    rest.push(Term::List(!is_any, factor));
+                    out.push((id, Term::List(is_any, rest)));
+                });
+            }
+            Rule::DoubleNegation => {}
+            Rule::Consensus => {
+                self.match_pairs(list, is_any, out, |out, i, j, lhs, rhs| {
+                    for &x in lhs {
+                        for &y in rhs.iter().filter(|&&y| self.is_negation(x, y)) {
+                            let lhs = lhs.iter().filter(|&&z| z != x);
+                            let rhs = rhs.iter().filter(|&&z| z != y);
+                            let mut cons: Vec<Id> = lhs.chain(rhs).copied().collect();
+                            cons.sort_unstable();
+                            cons.dedup();
+
+                            let found = match cons.as_slice() {
+                                [] => None,
+                                [z] => Some(*z),
+                                _ => self.lookup(if is_any { ENode::All(cons) } else { ENode::Any(cons) }),
+                            };
+                            let pos = found.and_then(|c| list.iter().position(|&z| z == c));
+                            if let Some(k) = pos.filter(|&k| k != i && k != j) {
+                                out.push((id, Term::list(is_any, without(&[k]))));
+                            }
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    /// Calls `f` for each pair of operands whose classes contain the dual list.
+    fn match_pairs<F>(&self, list: &[Id], is_any: bool, out: &mut Matches, mut f: F)
+    where
+        F: FnMut(&mut Matches, usize, usize, &[Id], &[Id]),
+    {
+        for i in 0..list.len() {
+            for j in i + 1..list.len() {
+                for lhs in self.lists(list[i], !is_any) {
+                    for rhs in self.lists(list[j], !is_any) {
+                        if out.is_full() {
+                            return;
+                        }
+                        f(out, i, j, lhs, rhs);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Extracts the cheapest expression of the class of `id`.
+    ///
+    /// The classes are settled cheapest first, each by a node whose children are settled already,
+    /// so the extracted expression is finite even if the classes form cycles.
+    pub fn extract(&self, id: Id, cost: &mut impl CostFunction<T>) -> Expr<T> {
+        // a node is `(class, index in the class)`
+        let mut waiting: HashMap<Id, Vec<(Id, usize)>> = HashMap::new();
+        let mut unsettled: HashMap<(Id, usize), usize> = HashMap::new();
+        let mut queue = BinaryHeap::new();
+
+        for (&class, nodes) in &self.classes {
+            for (i, node) in nodes.iter().enumerate() {
+                let children = node.children();
+                if children.is_empty() {
+                    queue.push(Reverse((cost.cost(node, &[]), class, i)));
+                } else {
+                    unsettled.insert((class, i), children.len());
+                    for &child in children {
+                        waiting.entry(self.find(child)).or_default().push((class, i));
+                    }
+                }
+            }
+        }
+
+        let mut best: HashMap<Id, (u64, &ENode<T>)> = HashMap::new();
+        while let Some(Reverse((c, class, i))) = queue.pop() {
+            if best.contains_key(&class) {
+                continue;
+            }
+            best.insert(class, (c, &self.classes[&class][i]));
+
+            for &(parent, j) in waiting.get(&class).into_iter().flatten() {
+                let count = unsettled.get_mut(&(parent, j)).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    let node = &self.classes[&parent][j];
+                    let children: Vec<u64> = node.children().iter().map(|x| best[&self.find(*x)].0).collect();
+                    queue.push(Reverse((cost.cost(node, &children), parent, j)));
+                }
+            }
+        }
+
+        self.build(self.find(id), &best)
+    }
+
+    /// Builds the expression of the best nodes below `id` without recursion.
+    fn build(&self, id: Id, best: &HashMap<Id, (u64, &ENode<T>)>) -> Expr<T> {
+        struct Frame<'a, T> {
+            node: &'a ENode<T>,
+            rest: slice::Iter<'a, Id>,
+            operands: Vec<Expr<T>>,
+        }
+
+        let frame = |id: Id| {
+            let node = best[&self.find(id)].1;
+            Frame {
+                node,
+                rest: node.children().iter(),
+                operands: Vec::new(),
+            }
+        };
+        let mut stack = vec![frame(id)];
+        loop {
+            let top = stack.last_mut().unwrap();
+            if let Some(&next) = top.rest.next() {
+                stack.push(frame(next));
+                continue;
+            }
+            let Frame { node, mut operands, .. } = stack.pop().unwrap();
+            let expr = match node {
+                ENode::Any(_) => ast::expr(ast::any(operands)),
+                ENode::All(_) => ast::expr(ast::all(operands)),
+                ENode::Not(_) => ast::expr(ast::not(operands.pop().unwrap())),
+                ENode::Var(var) => ast::expr(ast::var(var.clone())),
+                ENode::Const(b) => Expr::Const(*b),
+            };
+            match stack.last_mut() {
+                Some(parent) => parent.operands.push(expr),
+                None => return expr,
+            }
+        }
+    }
+}
+
+impl<T> Default for EGraph<T>
+where
+    T: Eq + Hash + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The cost of an e-node given the costs of its children.
+///
+/// The cost of a node should not be less than the cost of any of its children.
+pub trait CostFunction<T> {
+    fn cost(&mut self, node: &ENode<T>, children: &[u64]) -> u64;
+}
+
+impl<T, F> CostFunction<T> for F
+where
+    F: FnMut(&ENode<T>, &[u64]) -> u64,
+{
+    fn cost(&mut self, node: &ENode<T>, children: &[u64]) -> u64 {
+        self(node, children)
+    }
+}
+
+/// Counts the nodes of an expression.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AstSize;
+
+impl<T> CostFunction<T> for AstSize {
+    fn cost(&mut self, _: &ENode<T>, children: &[u64]) -> u64 {
+        1 + children.iter().sum::<u64>()
+    }
+}
+
+/// Simplifies an expression by equality saturation.
+#[derive(Debug, Clone)]
+pub struct Optimizer<C> {
+    rules: Vec<Rule>,
+    cost: C,
+    iter_limit: usize,
+    node_limit: usize,
+}
+
+impl Optimizer<AstSize> {
+    pub const DEFAULT_ITER_LIMIT: usize = 8;
+    pub const DEFAULT_NODE_LIMIT: usize = 5_000;
+
+    pub fn new() -> Self {
+        Self {
+            rules: Rule::ALL.to_vec(),
+            cost: AstSize,
+            iter_limit: Self::DEFAULT_ITER_LIMIT,
+            node_limit: Self::DEFAULT_NODE_LIMIT,
+        }
+    }
+}
+
+impl Default for Optimizer<AstSize> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> Optimizer<C> {
+    pub fn rules(mut self, rules: &[Rule]) -> Self {
+        self.rules = rules.to_vec();
+        self
+    }
+
+    pub fn cost<D>(self, cost: D) -> Optimizer<D> {
+        Optimizer {
+            rules: self.rules,
+            cost,
+            iter_limit: self.iter_limit,
+            node_limit: self.node_limit,
+        }
+    }
+
+    /// Sets the maximum number of saturation iterations. Each iteration applies every rule once.
+    pub fn iter_limit(mut self, n: usize) -> Self {
+        self.iter_limit = n;
+        self
+    }
+
+    /// Stops the saturation when the e-graph has more than `n` nodes.
+    pub fn node_limit(mut self, n: usize) -> Self {
+        self.node_limit = n;
+        self
+    }
+
+    pub fn run<T>(&mut self, expr: &Expr<T>) -> Expr<T>
+    where
+        T: Eq + Hash + Clone,
+        C: CostFunction<T>,
+    {
+        let mut egraph = EGraph::new();
+        let root = egraph.add_expr(expr);
+        for _ in 0..self.iter_limit {
+            if !egraph.apply(&self.rules, self.node_limit) || egraph.num_nodes() > self.node_limit {
+                break;
+            }
+        }
+        egraph.extract(root, &mut self.cost)
+    }
+}
+
+/// Simplifies an expression by equality saturation with all rules, minimizing the number of nodes.
+pub fn optimize<T>(expr: &Expr<T>) -> Expr<T>
+where
+    T: Eq + Hash + Clone,
+{
+    Optimizer::new().run(expr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    #[test]
+    fn saturate() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        let check = |src: Expr<u32>, expected: &str| {
+            assert_eq!(optimize(&src).to_string(), expected);
+        };
+
+        check(expr(any((all((x0, x1)), all((x0, not(x1)))))), "0");
+        check(expr(not(all((not(x0), not(x1))))), "any(0, 1)");
+        check(
+            expr(any((all((x0, x1)), all((not(x0), x2)), all((x1, x2))))),
+            "any(all(0, 1), all(not(0), 2))",
+        );
+        check(expr(all((x0, any((x0, x1)), not(not(x2))))), "all(0, 2)");
+    }
+
+    #[test]
+    fn cost() {
+        let src: Expr<u32> = expr(all((not(var(0)), not(var(1)))));
+        assert_eq!(optimize(&src).to_string(), "not(any(0, 1))");
+
+        let no_any = |node: &ENode<u32>, children: &[u64]| {
+            let own = if matches!(node, ENode::Any(_)) { 100 } else { 1 };
+            own + children.iter().sum::<u64>()
+        };
+        let dst = Optimizer::new().cost(no_any).run(&src);
+        assert_eq!(dst.to_string(), "all(not(0), not(1))");
+    }
+
+    #[test]
+    fn cycle() {
+        // `0 = all(0, 0)`: the class of `0` contains a node whose child is the class itself
+        let mut egraph = EGraph::new();
+        let x = egraph.add(ENode::Var(0_u32));
+        let y = egraph.add(ENode::All(vec![x, x]));
+        egraph.union(x, y);
+        egraph.rebuild();
+        assert_eq!(egraph.num_classes(), 1);
+
+        let mut free_all = |node: &ENode<u32>, children: &[u64]| match node {
+            ENode::All(_) => 0,
+            _ => 1 + children.iter().sum::<u64>(),
+        };
+        assert_eq!(egraph.extract(x, &mut AstSize).to_string(), "0");
+        assert_eq!(egraph.extract(x, &mut free_all).to_string(), "0");
+    }
+}
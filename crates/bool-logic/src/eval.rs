@@ -1,15 +1,324 @@
 use crate::ast::{All, Any, Expr, Not, Var};
+#[cfg(feature = "std")]
+use crate::bdd::Bdd;
+#[cfg(feature = "std")]
+use crate::sat::{self, Encoder};
+use crate::transform::{EvalConst, FlattenSingle};
 
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+use core::slice;
+
+/// Evaluates `expr` with the value of each variable given by `f`.
+///
+/// The evaluation is iterative and short-circuiting, so it works on arbitrarily deep expressions.
 pub fn eval_with<T, F>(expr: &Expr<T>, f: &F) -> bool
 where
     F: for<'v> Fn(&'v T) -> bool,
 {
-    match expr {
-        Expr::Any(Any(list)) => list.iter().any(|e| eval_with(e, f)),
-        Expr::All(All(list)) => list.iter().all(|e| eval_with(e, f)),
-        Expr::Not(Not(not)) => !eval_with(not, f),
-        Expr::Var(Var(var)) => f(var),
-        Expr::Const(b) => *b,
+    enum Frame<'a, T> {
+        Any(slice::Iter<'a, Expr<T>>),
+        All(slice::Iter<'a, Expr<T>>),
+        Not,
+    }
+
+    let mut stack: Vec<Frame<'_, T>> = Vec::new();
+    let mut cur = expr;
+    'descend: loop {
+        let mut val = match cur {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                let mut rest = list.iter();
+                match rest.next() {
+                    Some(first) => {
+                        stack.push(if cur.is_any() {
+                            Frame::Any(rest)
+                        } else {
+                            Frame::All(rest)
+                        });
+                        cur = first;
+                        continue 'descend;
+                    }
+                    None => cur.is_all(),
+                }
+            }
+            Expr::Not(Not(not)) => {
+                stack.push(Frame::Not);
+                cur = not;
+                continue 'descend;
+            }
+            Expr::Var(Var(var)) => f(var),
+            Expr::Const(b) => *b,
+        };
+
+        loop {
+            let Some(frame) = stack.last_mut() else { return val };
+            let rest = match frame {
+                Frame::Not => {
+                    val = !val;
+                    None
+                }
+                // continue with the next operand unless this one decided the result
+                Frame::Any(rest) if !val => Some(rest),
+                Frame::All(rest) if val => Some(rest),
+                Frame::Any(_) | Frame::All(_) => None,
+            };
+            if let Some(next) = rest.and_then(Iterator::next) {
+                cur = next;
+                continue 'descend;
+            }
+            stack.pop();
+        }
+    }
+}
+
+/// An algebra to evaluate expressions in.
+///
+/// `any` is folded with `add` and `all` with `mul`.
+pub trait Semiring: Sized {
+    fn zero() -> Self;
+    fn one() -> Self;
+    fn add(self, rhs: Self) -> Self;
+    fn mul(self, rhs: Self) -> Self;
+}
+
+impl Semiring for bool {
+    fn zero() -> Self {
+        false
+    }
+
+    fn one() -> Self {
+        true
+    }
+
+    fn add(self, rhs: Self) -> Self {
+        self || rhs
+    }
+
+    fn mul(self, rhs: Self) -> Self {
+        self && rhs
+    }
+}
+
+macro_rules! impl_semiring_for_num {
+    ($($ty:ty),*) => {$(
+        impl Semiring for $ty {
+            fn zero() -> Self {
+                0 as $ty
+            }
+
+            fn one() -> Self {
+                1 as $ty
+            }
+
+            fn add(self, rhs: Self) -> Self {
+                self + rhs
+            }
+
+            fn mul(self, rhs: Self) -> Self {
+                self * rhs
+            }
+        }
+    )*};
+}
+
+impl_semiring_for_num!(u32, u64, u128, usize, f32, f64);
+
+/// Evaluates `expr` in the semiring `S`.
+///
+/// Semirings have no negation, so `not` is pushed down to the variables by De Morgan's laws
+/// and `f` is called with each variable and its polarity (`false` if negated).
+pub fn eval_in<S, T, F>(expr: &Expr<T>, f: &F) -> S
+where
+    S: Semiring,
+    F: for<'v> Fn(&'v T, bool) -> S,
+{
+    struct Frame<'a, T, S> {
+        rest: slice::Iter<'a, Expr<T>>,
+        acc: Option<S>,
+        sum: bool,
+        pos: bool,
+    }
+
+    let mut stack: Vec<Frame<'_, T, S>> = Vec::new();
+    let (mut cur, mut pos) = (expr, true);
+    'descend: loop {
+        let mut val = match cur {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => {
+                let sum = cur.is_any() == pos;
+                let acc = if sum { S::zero() } else { S::one() };
+                let mut rest = list.iter();
+                match rest.next() {
+                    Some(first) => {
+                        let acc = Some(acc);
+                        stack.push(Frame { rest, acc, sum, pos });
+                        cur = first;
+                        continue 'descend;
+                    }
+                    None => acc,
+                }
+            }
+            Expr::Not(Not(not)) => {
+                (cur, pos) = (not, !pos);
+                continue 'descend;
+            }
+            Expr::Var(Var(var)) => f(var, pos),
+            Expr::Const(b) if *b == pos => S::one(),
+            Expr::Const(_) => S::zero(),
+        };
+
+        loop {
+            let Some(frame) = stack.last_mut() else { return val };
+            let acc = frame.acc.take().unwrap();
+            let acc = if frame.sum { acc.add(val) } else { acc.mul(val) };
+            if let Some(next) = frame.rest.next() {
+                frame.acc = Some(acc);
+                (cur, pos) = (next, frame.pos);
+                continue 'descend;
+            }
+            stack.pop();
+            val = acc;
+        }
+    }
+}
+
+/// Returns the exact probability that `expr` is true
+/// if each variable is independently true with probability `p(var)`.
+///
+/// Variables shared between operands are handled correctly by evaluating over a BDD.
+#[cfg(feature = "std")]
+pub fn probability<T, F>(expr: &Expr<T>, p: &F) -> f64
+where
+    T: Eq + Hash + Clone,
+    F: for<'v> Fn(&'v T) -> f64,
+{
+    let mut bdd = Bdd::new();
+    let f = bdd.from_expr(expr);
+    bdd.probability(f, p)
+}
+
+/// Substitutes the known variables and returns the residual expression after constant folding.
+///
+/// The folding runs to a fixpoint.
+pub fn partial_eval<T, F>(expr: &Expr<T>, f: &F) -> Expr<T>
+where
+    T: Clone,
+    F: for<'v> Fn(&'v T) -> Option<bool>,
+{
+    let mut expr = expr.clone().substitute(|var| f(var).map(Expr::Const));
+    loop {
+        let mut changed = EvalConst::run(&mut expr);
+        changed |= FlattenSingle::run(&mut expr);
+        if !changed {
+            break;
+        }
+    }
+    expr
+}
+
+/// A truth assignment of variables.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment<T>(pub Vec<(T, bool)>);
+
+impl<T> Assignment<T> {
+    pub fn iter(&self) -> impl Iterator<Item = (&T, bool)> {
+        self.0.iter().map(|(var, val)| (var, *val))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<T: Eq> Assignment<T> {
+    pub fn get(&self, var: &T) -> Option<bool> {
+        self.0.iter().find(|(x, _)| x == var).map(|(_, val)| *val)
+    }
+
+    /// Evaluates `expr` under the assignment. Unassigned variables are treated as `false`.
+    pub fn eval(&self, expr: &Expr<T>) -> bool {
+        eval_with(expr, &|var| self.get(var).unwrap_or(false))
+    }
+}
+
+/// `expr` has no satisfying assignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unsatisfiable;
+
+impl fmt::Display for Unsatisfiable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expression is unsatisfiable")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Unsatisfiable {}
+
+// The queries below return `Ok` if the answer is yes and `Err` if it is no,
+// with the assignment which decides the answer on whichever side has one.
+
+/// Returns `Ok` with a satisfying assignment, or `Err` if `expr` has none.
+#[cfg(feature = "std")]
+pub fn is_satisfiable<T>(expr: &Expr<T>) -> Result<Assignment<T>, Unsatisfiable>
+where
+    T: Eq + Hash + Clone,
+{
+    sat::solve(expr).ok_or(Unsatisfiable)
+}
+
+/// Returns `Err` with a falsifying assignment if `expr` is not always true.
+#[cfg(feature = "std")]
+pub fn is_tautology<T>(expr: &Expr<T>) -> Result<(), Assignment<T>>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut encoder = Encoder::new();
+    let lit = encoder.encode(expr);
+    encoder.add_clause(&[!lit]);
+    no_assignment(encoder)
+}
+
+/// Returns `Err` with an assignment under which `lhs` and `rhs` differ.
+#[cfg(feature = "std")]
+pub fn is_equivalent<T>(lhs: &Expr<T>, rhs: &Expr<T>) -> Result<(), Assignment<T>>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut encoder = Encoder::new();
+    let lhs = encoder.encode(lhs);
+    let rhs = encoder.encode(rhs);
+    encoder.add_clause(&[lhs, rhs]);
+    encoder.add_clause(&[!lhs, !rhs]);
+    no_assignment(encoder)
+}
+
+/// Returns `Err` with an assignment under which `lhs` holds but `rhs` does not.
+#[cfg(feature = "std")]
+pub fn implies<T>(lhs: &Expr<T>, rhs: &Expr<T>) -> Result<(), Assignment<T>>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut encoder = Encoder::new();
+    let lhs = encoder.encode(lhs);
+    let rhs = encoder.encode(rhs);
+    encoder.add_clause(&[lhs]);
+    encoder.add_clause(&[!rhs]);
+    no_assignment(encoder)
+}
+
+#[cfg(feature = "std")]
+fn no_assignment<T>(mut encoder: Encoder<T>) -> Result<(), Assignment<T>>
+where
+    T: Eq + Hash + Clone,
+{
+    match encoder.solve() {
+        Some(assign) => Err(assign),
+        None => Ok(()),
     }
 }
 
@@ -32,4 +341,119 @@ mod tests {
             assert_eq!(lhs, rhs, "i = {i}");
         }
     }
+
+    #[test]
+    fn partial() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+        let src = expr(any((all((x0, x1)), all((not(x0), x2)), not(any((x1,))))));
+
+        let known = |x: &u32| if *x == 0 { Some(true) } else { None };
+        let dst = partial_eval(&src, &known);
+        assert_eq!(dst.to_string(), "any(1, not(1))");
+
+        let known = |x: &u32| if *x == 2 { None } else { Some(false) };
+        let dst = partial_eval(&src, &known);
+        assert_eq!(dst.to_string(), "true");
+
+        let dst = partial_eval(&src, &|_| None);
+        assert_eq!(dst.to_string(), "any(all(0, 1), all(not(0), 2), not(1))");
+
+        // nested constants
+        let src = expr(all((any((const_(false),)), x0)));
+        assert_eq!(partial_eval(&src, &|_| None).to_string(), "false");
+
+        let src = expr(any((all((x0, any((x1,)))), not(not(all((x2, const_(true))))))));
+        let dst = partial_eval(&src, &|x: &u32| (*x != 2).then_some(*x == 0));
+        assert_eq!(dst.to_string(), "2");
+
+        let dst = partial_eval(&src, &|x: &u32| (*x == 1).then_some(true));
+        assert_eq!(dst.to_string(), "any(0, 2)");
+    }
+
+    #[test]
+    fn semiring() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+        let x: Expr<u32> = expr(any((all((x0, x1)), not(all((x1, not(x2)))), x2)));
+
+        for i in 0..8 {
+            let assign = |x: &u32| ((i >> x) & 1) == 1;
+            let lhs: bool = eval_in(&x, &|x, pos| assign(x) == pos);
+            assert_eq!(lhs, eval_with(&x, &assign), "i = {i}");
+        }
+
+        // the number of terms in the expanded disjunctive normal form
+        let terms: u64 = eval_in(&x, &|_, _| 1);
+        assert_eq!(terms, 1 + 2 + 1);
+
+        /// The cheapest way to satisfy an expression.
+        #[derive(Debug, PartialEq)]
+        struct MinCost(u32);
+
+        impl Semiring for MinCost {
+            fn zero() -> Self {
+                MinCost(u32::MAX)
+            }
+
+            fn one() -> Self {
+                MinCost(0)
+            }
+
+            fn add(self, rhs: Self) -> Self {
+                MinCost(self.0.min(rhs.0))
+            }
+
+            fn mul(self, rhs: Self) -> Self {
+                MinCost(self.0.saturating_add(rhs.0))
+            }
+        }
+
+        let cost = |x: &u32, pos: bool| if pos { MinCost(x + 1) } else { MinCost(0) };
+        assert_eq!(eval_in(&x, &cost), MinCost(0));
+        assert_eq!(eval_in(&expr(all((x0, x1, any((x2, x0))))), &cost), MinCost(4));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn probability() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+        let p = |&x: &u32| [0.5, 0.25, 0.8][x as usize];
+
+        // multiplying over the operands would give 0.5 * (1 - 0.5 * 0.75)
+        let x: Expr<u32> = expr(all((x0, any((x0, x1)))));
+        assert!((super::probability(&x, &p) - 0.5).abs() < 1e-9);
+
+        let x: Expr<u32> = expr(any((all((x0, x1)), all((x2, not(x1))))));
+        assert!((super::probability(&x, &p) - (0.5 * 0.25 + 0.8 * 0.75)).abs() < 1e-9);
+
+        assert_eq!(super::probability(&expr(any((x0, not(x0)))), &p), 1.0);
+        assert_eq!(super::probability(&expr(const_(false)), &p), 0.0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn semantic_queries() {
+        let (x0, x1) = (var(0), var(1));
+
+        let x = expr(all((x0, not(x1))));
+        let model = is_satisfiable(&x).unwrap();
+        assert!(model.eval(&x));
+        assert_eq!(is_satisfiable(&expr(all((x0, not(x0))))), Err(Unsatisfiable));
+        assert!(is_tautology(&expr(any((x0, not(x0))))).is_ok());
+
+        let cex = is_tautology(&x).unwrap_err();
+        assert!(!cex.eval(&x));
+
+        let lhs = expr(not(all((x0, x1))));
+        let rhs = expr(any((not(x0), not(x1))));
+        assert!(is_equivalent(&lhs, &rhs).is_ok());
+
+        let lhs = expr(all((x0, x1)));
+        let rhs = expr(x0);
+        assert!(implies(&lhs, &rhs).is_ok());
+
+        let cex = implies(&rhs, &lhs).unwrap_err();
+        assert!(cex.eval(&rhs));
+        assert!(!cex.eval(&lhs));
+        assert_eq!(cex.get(&1), Some(false));
+    }
 }
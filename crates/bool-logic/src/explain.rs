@@ -0,0 +1,214 @@
+use crate::ast::{All, Any, Expr, Not};
+
+use alloc::borrow::ToOwned;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::{self, Write as _};
+
+/// A rule which changed a subexpression.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event<T> {
+    /// The name of the pass or rewrite rule.
+    pub rule: String,
+    /// The operand indices from the root to the changed subexpression.
+    pub path: Vec<usize>,
+    pub before: Expr<T>,
+    pub after: Expr<T>,
+}
+
+/// A record of the rules which fired, in order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace<T> {
+    events: Vec<Event<T>>,
+}
+
+impl<T> Trace<T> {
+    pub fn new() -> Self {
+        Self { events: Vec::new() }
+    }
+
+    pub fn events(&self) -> &[Event<T>] {
+        &self.events
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn push(&mut self, event: Event<T>) {
+        self.events.push(event);
+    }
+}
+
+impl<T> Default for Trace<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Trace<T>
+where
+    T: Clone + PartialEq,
+{
+    /// Records the subexpressions changed by `rule` from `before` to `after`.
+    /// Returns `false` if nothing changed.
+    pub fn record(&mut self, rule: &str, before: &Expr<T>, after: &Expr<T>) -> bool {
+        let len = self.events.len();
+        self.diff(rule, before, after, &mut Vec::new());
+        self.events.len() != len
+    }
+
+    fn diff(&mut self, rule: &str, before: &Expr<T>, after: &Expr<T>, path: &mut Vec<usize>) {
+        if before == after {
+            return;
+        }
+        match (before, after) {
+            (Expr::Any(Any(lhs)), Expr::Any(Any(rhs))) | (Expr::All(All(lhs)), Expr::All(All(rhs)))
+                if lhs.len() == rhs.len() =>
+            {
+                for (i, (lhs, rhs)) in lhs.iter().zip(rhs).enumerate() {
+                    path.push(i);
+                    self.diff(rule, lhs, rhs, path);
+                    path.pop();
+                }
+            }
+            (Expr::Not(Not(lhs)), Expr::Not(Not(rhs))) => {
+                path.push(0);
+                self.diff(rule, lhs, rhs, path);
+                path.pop();
+            }
+            _ => self.events.push(Event {
+                rule: rule.to_owned(),
+                path: path.clone(),
+                before: before.clone(),
+                after: after.clone(),
+            }),
+        }
+    }
+}
+
+/// Renders the trace as a step-by-step explanation.
+impl<T> fmt::Display for Trace<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, event) in self.events.iter().enumerate() {
+            writeln!(f, "{}. {} at {:?}", i + 1, event.rule, event.path)?;
+            writeln!(f, "   - {}", event.before)?;
+            writeln!(f, "   + {}", event.after)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T> Trace<T>
+where
+    T: fmt::Display,
+{
+    /// Renders the trace as a JSON array of events.
+    pub fn to_json(&self) -> String {
+        let mut s = String::from("[");
+        for (i, event) in self.events.iter().enumerate() {
+            if i != 0 {
+                s.push(',');
+            }
+            s.push_str("\n  {\"rule\": ");
+            push_json_str(&mut s, &event.rule);
+            s.push_str(", \"path\": [");
+            for (j, idx) in event.path.iter().enumerate() {
+                if j != 0 {
+                    s.push_str(", ");
+                }
+                write!(s, "{idx}").unwrap();
+            }
+            s.push_str("], \"before\": ");
+            push_json_str(&mut s, &event.before.to_string());
+            s.push_str(", \"after\": ");
+            push_json_str(&mut s, &event.after.to_string());
+            s.push('}');
+        }
+        if !self.events.is_empty() {
+            s.push('\n');
+        }
+        s.push(']');
+        s
+    }
+}
+
+fn push_json_str(s: &mut String, value: &str) {
+    s.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            c if c.is_control() => write!(s, "\\u{:04x}", c as u32).unwrap(),
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+    use crate::pipeline::Pipeline;
+    use crate::rewrite::Rewrites;
+    use crate::transform::*;
+
+    #[test]
+    fn pipeline() {
+        let mut x: Expr<u32> = expr(all((var(0), any((var(1), const_(true))), not(not(var(2))))));
+
+        let mut trace = Trace::new();
+        Pipeline::new()
+            .pass(EvalConst)
+            .pass(FlattenSingle)
+            .run_traced(&mut x, &mut trace);
+
+        assert_eq!(x.to_string(), "all(0, 2)");
+        assert_eq!(
+            trace.to_string(),
+            [
+                "1. EvalConst at []",
+                "   - all(0, any(1, true), not(not(2)))",
+                "   + all(0, not(not(2)))",
+                "2. FlattenSingle at [1]",
+                "   - not(not(2))",
+                "   + 2",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn rewrites() {
+        let mut x: Expr<u32> = expr(any((not(not(var(0))), all((var(1), not(not(var(2))))))));
+
+        let mut trace = Trace::new();
+        Rewrites::new()
+            .rule("not(not(?x)) => ?x")
+            .rewrite_traced(&mut x, &mut trace);
+
+        assert_eq!(x.to_string(), "any(0, all(1, 2))");
+        assert_eq!(
+            trace.to_json(),
+            [
+                r#"["#,
+                r#"  {"rule": "not(not(?x)) => ?x", "path": [0], "before": "not(not(0))", "after": "0"},"#,
+                r#"  {"rule": "not(not(?x)) => ?x", "path": [1, 1], "before": "not(not(2))", "after": "2"}"#,
+                r#"]"#,
+            ]
+            .join("\n")
+        );
+        assert_eq!(Trace::<u32>::new().to_json(), "[]");
+    }
+}
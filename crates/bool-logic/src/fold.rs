@@ -0,0 +1,123 @@
+use crate::ast::{All, Any, Expr, Not, Var};
+use crate::visit_mut::detach;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Consumes an `Expr<T>` and rebuilds it as an `Expr<U>`.
+///
+/// The `fold_*` functions recurse once per level of nesting, so a folder built on them is limited by the call stack.
+pub trait Fold<T, U> {
+    fn fold_expr(&mut self, expr: Expr<T>) -> Expr<U> {
+        fold_expr(self, expr)
+    }
+
+    fn fold_any(&mut self, any: Any<T>) -> Any<U> {
+        Any(fold_expr_list(self, any.into_inner()))
+    }
+
+    fn fold_all(&mut self, all: All<T>) -> All<U> {
+        All(fold_expr_list(self, all.into_inner()))
+    }
+
+    fn fold_not(&mut self, not: Not<T>) -> Not<U> {
+        Not(Box::new(fold_not(self, not.into_inner())))
+    }
+
+    fn fold_var(&mut self, var: Var<T>) -> Var<U>;
+
+    fn fold_const(&mut self, b: bool) -> bool {
+        b
+    }
+}
+
+pub fn fold_expr<F, T, U>(f: &mut F, expr: Expr<T>) -> Expr<U>
+where
+    F: Fold<T, U> + ?Sized,
+{
+    match expr {
+        Expr::Any(any) => Expr::Any(f.fold_any(any)),
+        Expr::All(all) => Expr::All(f.fold_all(all)),
+        Expr::Not(not) => Expr::Not(f.fold_not(not)),
+        Expr::Var(var) => Expr::Var(f.fold_var(var)),
+        Expr::Const(b) => Expr::Const(f.fold_const(b)),
+    }
+}
+
+pub fn fold_expr_list<F, T, U>(f: &mut F, list: Vec<Expr<T>>) -> Vec<Expr<U>>
+where
+    F: Fold<T, U> + ?Sized,
+{
+    list.into_iter().map(|expr| f.fold_expr(expr)).collect()
+}
+
+pub fn fold_not<F, T, U>(f: &mut F, not: Expr<T>) -> Expr<U>
+where
+    F: Fold<T, U> + ?Sized,
+{
+    f.fold_expr(not)
+}
+
+/// Consumes `expr` bottom-up without recursion, stopping at the first error.
+///
+/// `f` receives each subexpression, with its operands moved out, and the results of the operands.
+pub(crate) fn try_postorder_into<T, R, E, F>(mut expr: Expr<T>, mut f: F) -> Result<R, E>
+where
+    F: FnMut(Expr<T>, Vec<R>) -> Result<R, E>,
+{
+    struct Frame<T, R> {
+        expr: Expr<T>,
+        results: Vec<R>,
+        rest: vec::IntoIter<Expr<T>>,
+    }
+
+    let mut stack: Vec<Frame<T, R>> = Vec::new();
+    loop {
+        let rest = detach(&mut expr).into_iter();
+        stack.push(Frame {
+            expr,
+            results: Vec::with_capacity(rest.len()),
+            rest,
+        });
+        loop {
+            let frame = stack.last_mut().unwrap();
+            if let Some(next) = frame.rest.next() {
+                expr = next;
+                break;
+            }
+            let Frame { expr, results, .. } = stack.pop().unwrap();
+            let result = f(expr, results)?;
+            match stack.last_mut() {
+                Some(parent) => parent.results.push(result),
+                None => return Ok(result),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    #[test]
+    fn rename() {
+        struct Rename;
+
+        impl Fold<u32, String> for Rename {
+            fn fold_var(&mut self, Var(x): Var<u32>) -> Var<String> {
+                Var(format!("x{x}"))
+            }
+
+            fn fold_const(&mut self, b: bool) -> bool {
+                !b
+            }
+        }
+
+        let x: Expr<u32> = expr(all((var(0), not(any((var(1), const_(true)))))));
+        let x = Rename.fold_expr(x);
+        assert_eq!(x.to_string(), "all(x0, not(any(x1, false)))");
+    }
+}
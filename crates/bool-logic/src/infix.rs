@@ -0,0 +1,287 @@
+//! Conventional infix syntax: `a && !b || (c && d)`.
+//!
+//! ```text
+//! or   := and ("||" and)*
+//! and  := not ("&&" not)*
+//! not  := "!" not | atom
+//! atom := "(" or ")" | "true" | "false" | var
+//! ```
+//!
+//! A variable is a run of characters other than whitespace and `()!&|`.
+
+use crate::ast::{self, All, Any, Expr, Not, Var};
+use crate::rewrite::ParseError;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+/// Parses an infix expression whose variables are parsed by `f`.
+///
+/// A chain of `||` or `&&` becomes a single `any` or `all`. Parenthesized operands stay nested.
+pub fn parse_with<T, F>(src: &str, f: F) -> Result<Expr<T>, ParseError>
+where
+    F: FnMut(&str) -> Option<T>,
+{
+    let mut parser = Parser { src, pos: 0, f };
+    let expr = parser.or()?;
+    parser.skip_ws();
+    if parser.pos != src.len() {
+        return parser.error("unexpected trailing input");
+    }
+    Ok(expr)
+}
+
+/// Parses an infix expression whose variables are parsed by [`FromStr`].
+pub fn parse<T: FromStr>(src: &str) -> Result<Expr<T>, ParseError> {
+    parse_with(src, |s| s.parse().ok())
+}
+
+struct Parser<'s, F> {
+    src: &'s str,
+    pos: usize,
+    f: F,
+}
+
+impl<'s, T, F> Parser<'s, F>
+where
+    F: FnMut(&str) -> Option<T>,
+{
+    fn error<U>(&self, msg: &'static str) -> Result<U, ParseError> {
+        Err(ParseError { pos: self.pos, msg })
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        if self.src[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr<T>, ParseError> {
+        let mut list = vec![self.and()?];
+        while self.eat("||") {
+            list.push(self.and()?);
+        }
+        Ok(if list.len() == 1 {
+            list.pop().unwrap()
+        } else {
+            ast::expr(ast::any(list))
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr<T>, ParseError> {
+        let mut list = vec![self.not()?];
+        while self.eat("&&") {
+            list.push(self.not()?);
+        }
+        Ok(if list.len() == 1 {
+            list.pop().unwrap()
+        } else {
+            ast::expr(ast::all(list))
+        })
+    }
+
+    fn not(&mut self) -> Result<Expr<T>, ParseError> {
+        if self.eat("!") {
+            return Ok(ast::expr(ast::not(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr<T>, ParseError> {
+        if self.eat("(") {
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return self.error("expected `)`");
+            }
+            return Ok(expr);
+        }
+
+        self.skip_ws();
+        let rest = &self.src[self.pos..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || "()!&|".contains(c))
+            .unwrap_or(rest.len());
+        let token = &rest[..len];
+        let expr = match token {
+            "" => return self.error("expected an expression"),
+            "true" => Expr::Const(true),
+            "false" => Expr::Const(false),
+            _ => match (self.f)(token) {
+                Some(var) => ast::expr(ast::var(var)),
+                None => return self.error("invalid variable"),
+            },
+        };
+        self.pos += len;
+        Ok(expr)
+    }
+}
+
+/// Formats an expression in infix syntax with the fewest parentheses allowed by precedence.
+///
+/// Nested lists of the same kind are printed without parentheses,
+/// so parsing the output gives an equivalent expression which may be flatter.
+pub struct Infix<'a, T, F> {
+    expr: &'a Expr<T>,
+    fmt_var: F,
+}
+
+type FmtVar<T> = fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result;
+
+/// Formats `expr` in infix syntax, printing variables by [`Display`](fmt::Display).
+pub fn infix<T: fmt::Display>(expr: &Expr<T>) -> Infix<'_, T, FmtVar<T>> {
+    infix_with(expr, |var, f| fmt::Display::fmt(var, f))
+}
+
+/// Formats `expr` in infix syntax, printing variables by `f`.
+pub fn infix_with<T, F>(expr: &Expr<T>, f: F) -> Infix<'_, T, F>
+where
+    F: Fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result,
+{
+    Infix { expr, fmt_var: f }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Or,
+    And,
+    Not,
+    Atom,
+}
+
+/// Skips the lists with a single operand, which are printed as the operand itself.
+fn unwrap_single<T>(mut expr: &Expr<T>) -> &Expr<T> {
+    while let Expr::Any(Any(list)) | Expr::All(All(list)) = expr {
+        match list.as_slice() {
+            [single] => expr = single,
+            _ => break,
+        }
+    }
+    expr
+}
+
+fn precedence<T>(expr: &Expr<T>) -> Prec {
+    match expr {
+        Expr::Any(Any(list)) if list.len() > 1 => Prec::Or,
+        Expr::All(All(list)) if list.len() > 1 => Prec::And,
+        Expr::Not(_) => Prec::Not,
+        _ => Prec::Atom,
+    }
+}
+
+impl<T, F> fmt::Display for Infix<'_, T, F>
+where
+    F: Fn(&T, &mut fmt::Formatter<'_>) -> fmt::Result,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        enum Item<'a, T> {
+            Expr(&'a Expr<T>, Prec),
+            Str(&'static str),
+        }
+
+        // the pending output in reverse order, with the lowest precedence allowed without parentheses
+        let mut stack: Vec<Item<'_, T>> = vec![Item::Expr(self.expr, Prec::Or)];
+        while let Some(item) = stack.pop() {
+            let (expr, min) = match item {
+                Item::Expr(expr, min) => (unwrap_single(expr), min),
+                Item::Str(s) => {
+                    f.write_str(s)?;
+                    continue;
+                }
+            };
+
+            let prec = precedence(expr);
+            if prec < min {
+                f.write_str("(")?;
+                stack.push(Item::Str(")"));
+            }
+
+            match expr {
+                Expr::Any(Any(list)) | Expr::All(All(list)) if prec != Prec::Atom => {
+                    let sep = if prec == Prec::Or { " || " } else { " && " };
+                    for (i, e) in list.iter().enumerate().rev() {
+                        stack.push(Item::Expr(e, prec));
+                        if i != 0 {
+                            stack.push(Item::Str(sep));
+                        }
+                    }
+                }
+                Expr::Any(_) => f.write_str("false")?,
+                Expr::All(_) => f.write_str("true")?,
+                Expr::Not(Not(not)) => {
+                    f.write_str("!")?;
+                    stack.push(Item::Expr(not, Prec::Not));
+                }
+                Expr::Var(Var(var)) => (self.fmt_var)(var, f)?,
+                Expr::Const(b) => write!(f, "{b}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    use alloc::format;
+    use alloc::string::{String, ToString};
+
+    #[test]
+    fn parse_infix() {
+        let x: Expr<String> = parse("a && !b || (c && d)").unwrap();
+        assert_eq!(x.to_string(), "any(all(a, not(b)), all(c, d))");
+
+        let x: Expr<String> = parse("!(a || b) && (c || true) && !!d").unwrap();
+        assert_eq!(x.to_string(), "all(not(any(a, b)), any(c, true), not(not(d)))");
+
+        let x: Expr<u32> = parse("0 && (1 && 2)").unwrap();
+        assert_eq!(x.to_string(), "all(0, all(1, 2))");
+
+        let err = parse::<u32>("0 && x").unwrap_err();
+        assert_eq!(err.to_string(), "invalid variable at position 5");
+        let err = parse::<u32>("(0 || 1").unwrap_err();
+        assert_eq!(err.to_string(), "expected `)` at position 7");
+        let err = parse::<u32>("0 &&").unwrap_err();
+        assert_eq!(err.to_string(), "expected an expression at position 4");
+
+        let ids = ["x", "y"];
+        let x = parse_with("x || !y", |s| ids.iter().position(|id| *id == s)).unwrap();
+        assert_eq!(x.to_string(), "any(0, not(1))");
+    }
+
+    #[test]
+    fn print_infix() {
+        let (a, b, c, d) = (var("a"), var("b"), var("c"), var("d"));
+        let print = |x: &Expr<&str>| format!("{}", infix(x));
+
+        let x = expr(any((all((a, not(b))), all((c, d)))));
+        assert_eq!(print(&x), "a && !b || c && d");
+
+        let x = expr(all((any((a, b)), not(all((c, d))), not(not(a)))));
+        assert_eq!(print(&x), "(a || b) && !(c && d) && !!a");
+
+        let x = expr(all((any((a,)), any((all((b, c)), any((d, const_(false))))), any(()))));
+        assert_eq!(print(&x), "a && (b && c || d || false) && false");
+
+        let src = "!(a || b && c) && (d || !a)";
+        let x: Expr<String> = parse(src).unwrap();
+        assert_eq!(format!("{}", infix(&x)), src);
+
+        let x: Expr<u32> = parse("0 || !1").unwrap();
+        let print = infix_with(&x, |v, f| write!(f, "x{v}"));
+        assert_eq!(print.to_string(), "x0 || !x1");
+    }
+}
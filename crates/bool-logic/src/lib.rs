@@ -1,9 +1,56 @@
+//! Boolean expressions and the passes which simplify them.
+//!
+//! # Features
+//!
+//! - `std` (default): enables the modules which depend on `std` hash maps:
+//!   `analysis`, `arena`, `axioms`, `bdd`, `diff`, `egraph`, `minimize`, `models` and `sat`,
+//!   together with `eval::probability` and the semantic queries of `eval`
+//!   (`is_satisfiable`, `is_tautology`, `is_equivalent` and `implies`).
+//!   It also implements `std::error::Error` for the error types.
+//! - `testing`: enables `testing`, the property-based checks of passes. It implies `std`.
+//!
+//! Without `std`, the crate is `no_std` and needs only `alloc`. The expression types, evaluation,
+//! traversals, normal forms, parsing and printing, and all passes of `transform`,
+//! `rewrite` and `pipeline` stay available.
+
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
 #![deny(clippy::all)]
 #![warn(clippy::dbg_macro, clippy::todo)]
 
+extern crate alloc;
+
 mod utils;
 
+#[cfg(feature = "std")]
+pub mod analysis;
+#[cfg(feature = "std")]
+pub mod arena;
 pub mod ast;
+#[cfg(feature = "std")]
+pub mod axioms;
+#[cfg(feature = "std")]
+pub mod bdd;
+#[cfg(feature = "std")]
+pub mod diff;
+#[cfg(feature = "std")]
+pub mod egraph;
 pub mod eval;
+pub mod explain;
+pub mod fold;
+pub mod infix;
+#[cfg(feature = "std")]
+pub mod minimize;
+#[cfg(feature = "std")]
+pub mod models;
+pub mod normal_form;
+pub mod pipeline;
+pub mod rewrite;
+#[cfg(feature = "std")]
+pub mod sat;
+
 pub mod transform;
+pub mod visit;
 pub mod visit_mut;
+
+#[cfg(feature = "testing")]
+pub mod testing;
@@ -0,0 +1,554 @@
+use crate::ast::{self, Expr};
+use crate::bdd::{Bdd, Node};
+use crate::eval::eval_with;
+use crate::normal_form::dnf;
+use crate::utils::collect_vars;
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// The largest number of variables for which [`Mode::Auto`] runs the exact algorithm.
+pub const EXACT_VAR_LIMIT: usize = 10;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Exact for at most [`EXACT_VAR_LIMIT`] variables, heuristic otherwise.
+    #[default]
+    Auto,
+    /// Quine–McCluskey prime implicants with a branch-and-bound cover.
+    ///
+    /// The cover is minimal only if the search finishes within its budget,
+    /// otherwise it is the best cover found so far.
+    /// Falls back to [`Mode::Heuristic`] for more than 16 variables, if there are too many implicants,
+    /// or if the budget runs out before any cover is found.
+    Exact,
+    /// Espresso-style expand, irredundant and reduce loop.
+    Heuristic,
+}
+
+/// A product term: a list of `(variable index, polarity)` sorted by variable index.
+pub(crate) type Cube = Vec<(usize, bool)>;
+
+/// Returns a minimal sum-of-products form of `expr`: `any(all(...), ...)`.
+///
+/// The result is exact only within the limits of [`Mode::Exact`].
+pub fn minimize_sop<T>(expr: &Expr<T>, mode: Mode) -> Expr<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let vars = collect_vars(expr);
+    let cover = minimize_cover(expr, &vars, mode);
+    sop_expr(&vars, cover)
+}
+
+/// Returns a minimal product-of-sums form of `expr`: `all(any(...), ...)`.
+///
+/// The result is exact only within the limits of [`Mode::Exact`].
+pub fn minimize_pos<T>(expr: &Expr<T>, mode: Mode) -> Expr<T>
+where
+    T: Eq + Hash + Clone,
+{
+    // `f = not(sop(not(f)))`
+    let neg = ast::expr(ast::not(expr.clone()));
+    let vars = collect_vars(expr);
+    let cover = minimize_cover(&neg, &vars, mode);
+    let cover = cover
+        .into_iter()
+        .map(|cube| cube.into_iter().map(|(v, b)| (v, !b)).collect());
+    pos_expr(&vars, cover.collect())
+}
+
+fn minimize_cover<T>(expr: &Expr<T>, vars: &[&T], mode: Mode) -> Vec<Cube>
+where
+    T: Eq + Hash + Clone,
+{
+    let exact = match mode {
+        Mode::Auto => vars.len() <= EXACT_VAR_LIMIT,
+        Mode::Exact => true,
+        Mode::Heuristic => false,
+    };
+    let exact_cover = if exact {
+        QuineMcCluskey::minimize(expr, vars)
+    } else {
+        None
+    };
+    let mut cover = exact_cover.unwrap_or_else(|| Espresso::minimize(expr, vars));
+    cover.sort();
+    cover
+}
+
+/// Returns the prime implicants of `expr` over `vars`, each with whether it is essential.
+///
+/// Returns `None` if there are too many variables or implicants.
+pub(crate) fn prime_implicants<T>(expr: &Expr<T>, vars: &[&T]) -> Option<Vec<(Cube, bool)>>
+where
+    T: Eq + Hash,
+{
+    let mut primes = QuineMcCluskey::prime_implicants(expr, vars)?;
+    primes.sort();
+    Some(primes)
+}
+
+fn cost(cover: &[Cube]) -> (usize, usize) {
+    (cover.len(), cover.iter().map(Vec::len).sum())
+}
+
+fn literal<T: Clone>(vars: &[&T], (v, b): (usize, bool)) -> Expr<T> {
+    let x = ast::expr(ast::var(vars[v].clone()));
+    if b {
+        x
+    } else {
+        ast::expr(ast::not(x))
+    }
+}
+
+fn sop_expr<T: Clone>(vars: &[&T], cover: Vec<Cube>) -> Expr<T> {
+    let terms = cover.into_iter().map(|cube| {
+        let mut lits: Vec<_> = cube.into_iter().map(|lit| literal(vars, lit)).collect();
+        match lits.len() {
+            0 => Expr::Const(true),
+            1 => lits.pop().unwrap(),
+            _ => ast::expr(ast::all(lits)),
+        }
+    });
+    let mut terms: Vec<_> = terms.collect();
+    match terms.len() {
+        0 => Expr::Const(false),
+        1 => terms.pop().unwrap(),
+        _ => ast::expr(ast::any(terms)),
+    }
+}
+
+fn pos_expr<T: Clone>(vars: &[&T], cover: Vec<Cube>) -> Expr<T> {
+    let clauses = cover.into_iter().map(|cube| {
+        let mut lits: Vec<_> = cube.into_iter().map(|lit| literal(vars, lit)).collect();
+        match lits.len() {
+            0 => Expr::Const(false),
+            1 => lits.pop().unwrap(),
+            _ => ast::expr(ast::any(lits)),
+        }
+    });
+    let mut clauses: Vec<_> = clauses.collect();
+    match clauses.len() {
+        0 => Expr::Const(true),
+        1 => clauses.pop().unwrap(),
+        _ => ast::expr(ast::all(clauses)),
+    }
+}
+
+struct QuineMcCluskey {
+    /// Prime implicants as `(value, mask)`. Bits set in `mask` are don't-cares.
+    primes: Vec<(u32, u32)>,
+    /// The indices of the primes covering each minterm.
+    covers: Vec<Vec<usize>>,
+    best: Option<((usize, usize), Vec<usize>)>,
+    budget: usize,
+}
+
+impl QuineMcCluskey {
+    const MAX_VARS: usize = 16;
+    const SEARCH_BUDGET: usize = 100_000;
+    /// The number of implicants generated while merging minterms,
+    /// which can be up to `3^n` for `n` variables.
+    const IMPLICANT_BUDGET: usize = 100_000;
+    /// The number of minterm and prime pairs checked for the covers.
+    const COVER_BUDGET: usize = 10_000_000;
+
+    /// Returns `None` if there are too many variables or implicants,
+    /// or if the budget runs out before any cover is found.
+    fn minimize<T: Eq + Hash>(expr: &Expr<T>, vars: &[&T]) -> Option<Vec<Cube>> {
+        let n = vars.len();
+        let mut qm = Self::new(expr, vars)?;
+        let mut covered = vec![false; qm.covers.len()];
+        qm.search(&mut covered, &mut Vec::new(), n);
+
+        let (_, chosen) = qm.best.take()?;
+        Some(chosen.into_iter().map(|p| qm.cube(p, n)).collect())
+    }
+
+    /// Returns all prime implicants, each with whether it is essential,
+    /// i.e. the only prime implicant covering some minterm.
+    fn prime_implicants<T: Eq + Hash>(expr: &Expr<T>, vars: &[&T]) -> Option<Vec<(Cube, bool)>> {
+        let n = vars.len();
+        let qm = Self::new(expr, vars)?;
+        let mut essential = vec![false; qm.primes.len()];
+        for cover in &qm.covers {
+            if let [p] = cover[..] {
+                essential[p] = true;
+            }
+        }
+        Some((0..qm.primes.len()).map(|p| (qm.cube(p, n), essential[p])).collect())
+    }
+
+    /// Returns `None` if there are too many variables or implicants.
+    fn new<T: Eq + Hash>(expr: &Expr<T>, vars: &[&T]) -> Option<Self> {
+        let n = vars.len();
+        if n > Self::MAX_VARS {
+            return None;
+        }
+
+        let index: HashMap<&T, usize> = vars.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+        let minterms: Vec<u32> = (0..(1_u32 << n))
+            .filter(|m| eval_with(expr, &|x| (m >> index[x]) & 1 == 1))
+            .collect();
+
+        let primes = Self::primes(&minterms, n)?;
+        if minterms.len().saturating_mul(primes.len()) > Self::COVER_BUDGET {
+            return None;
+        }
+        let covers = minterms
+            .iter()
+            .map(|&m| (0..primes.len()).filter(|&p| m & !primes[p].1 == primes[p].0).collect())
+            .collect();
+
+        Some(Self {
+            primes,
+            covers,
+            best: None,
+            budget: Self::SEARCH_BUDGET,
+        })
+    }
+
+    fn cube(&self, p: usize, n: usize) -> Cube {
+        let (value, mask) = self.primes[p];
+        (0..n)
+            .filter(|&v| (mask >> v) & 1 == 0)
+            .map(|v| (v, (value >> v) & 1 == 1))
+            .collect()
+    }
+
+    fn primes(minterms: &[u32], n: usize) -> Option<Vec<(u32, u32)>> {
+        let mut primes = Vec::new();
+        let mut current: HashSet<(u32, u32)> = minterms.iter().map(|&m| (m, 0)).collect();
+        let mut generated = current.len();
+
+        while !current.is_empty() {
+            let mut next = HashSet::new();
+            let mut combined = HashSet::new();
+
+            for &(value, mask) in &current {
+                for bit in (0..n).map(|v| 1 << v).filter(|bit| mask & bit == 0) {
+                    if current.contains(&(value ^ bit, mask)) {
+                        next.insert((value & !bit, mask | bit));
+                        combined.insert((value, mask));
+                    }
+                }
+                if generated + next.len() > Self::IMPLICANT_BUDGET {
+                    return None;
+                }
+            }
+            generated += next.len();
+
+            let mut rest: Vec<_> = current.difference(&combined).copied().collect();
+            rest.sort_unstable();
+            primes.extend(rest);
+            current = next;
+        }
+
+        Some(primes)
+    }
+
+    fn literals(&self, p: usize, n: usize) -> usize {
+        n - self.primes[p].1.count_ones() as usize
+    }
+
+    fn chosen_cost(&self, chosen: &[usize], n: usize) -> (usize, usize) {
+        (chosen.len(), chosen.iter().map(|&p| self.literals(p, n)).sum())
+    }
+
+    /// Branches on the uncovered minterm with the fewest candidate primes.
+    fn search(&mut self, covered: &mut Vec<bool>, chosen: &mut Vec<usize>, n: usize) {
+        if self.budget == 0 {
+            return;
+        }
+        self.budget -= 1;
+
+        let cost = self.chosen_cost(chosen, n);
+        let target = (0..covered.len())
+            .filter(|&m| !covered[m])
+            .min_by_key(|&m| self.covers[m].len());
+
+        let Some(m) = target else {
+            if !matches!(&self.best, Some((best, _)) if *best <= cost) {
+                self.best = Some((cost, chosen.clone()));
+            }
+            return;
+        };
+
+        if let Some((best, _)) = &self.best {
+            if (cost.0 + 1, cost.1 + 1) >= *best {
+                return;
+            }
+        }
+
+        let mut candidates = self.covers[m].clone();
+        candidates.sort_by_key(|&p| (Reverse(self.primes[p].1.count_ones()), p));
+
+        for p in candidates {
+            let newly: Vec<usize> = (0..covered.len())
+                .filter(|&i| !covered[i] && self.covers[i].contains(&p))
+                .collect();
+            newly.iter().for_each(|&i| covered[i] = true);
+            chosen.push(p);
+
+            self.search(covered, chosen, n);
+
+            chosen.pop();
+            newly.iter().for_each(|&i| covered[i] = false);
+        }
+    }
+}
+
+/// An Espresso-style minimizer which uses a BDD of the function as the containment oracle.
+struct Espresso<'a, T> {
+    bdd: Bdd<T>,
+    vars: &'a [&'a T],
+    on: Node,
+    off: Node,
+}
+
+impl<'a, T> Espresso<'a, T>
+where
+    T: Eq + Hash + Clone,
+{
+    const MAX_ITERATIONS: usize = 8;
+
+    /// The largest DNF of the input which seeds the cover.
+    const DNF_LIMIT: usize = 4096;
+
+    fn minimize(expr: &Expr<T>, vars: &'a [&'a T]) -> Vec<Cube> {
+        let mut bdd = Bdd::new();
+        for &v in vars {
+            bdd.var(v);
+        }
+
+        let on = bdd.from_expr(expr);
+        let off = bdd.not(on);
+
+        let index: HashMap<&T, usize> = vars.iter().enumerate().map(|(i, v)| (*v, i)).collect();
+        let mut cover = match Self::initial_cover(expr, &index) {
+            Some(cover) => cover,
+            None => bdd
+                .cubes(on)
+                .into_iter()
+                .map(|cube| {
+                    let mut cube: Cube = cube.into_iter().map(|(x, b)| (index[x], b)).collect();
+                    cube.sort_unstable();
+                    cube
+                })
+                .collect(),
+        };
+
+        let mut this = Self { bdd, vars, on, off };
+
+        this.expand(&mut cover, 0);
+        this.irredundant(&mut cover);
+        let mut best = cover.clone();
+
+        for round in 1..=Self::MAX_ITERATIONS {
+            this.reduce(&mut cover);
+            this.expand(&mut cover, round);
+            this.irredundant(&mut cover);
+
+            if cost(&cover) < cost(&best) {
+                best = cover.clone();
+            } else {
+                break;
+            }
+        }
+
+        best
+    }
+
+    /// Returns the cubes of the DNF of `expr` without the contradictory ones,
+    /// or `None` if the DNF is too large. The BDD paths are used instead then.
+    fn initial_cover(expr: &Expr<T>, index: &HashMap<&T, usize>) -> Option<Vec<Cube>> {
+        let dnf = dnf(expr.clone(), Self::DNF_LIMIT).ok()?;
+        let mut cover = Vec::with_capacity(dnf.0.len());
+        for lits in dnf.0 {
+            let mut cube: Cube = lits.iter().map(|lit| (index[&lit.var], lit.positive)).collect();
+            cube.sort_unstable();
+            cube.dedup();
+            if cube.windows(2).all(|w| w[0].0 != w[1].0) {
+                cover.push(cube);
+            }
+        }
+        Some(cover)
+    }
+
+    fn cube_node(&mut self, cube: &[(usize, bool)]) -> Node {
+        cube.iter().fold(Node::TRUE, |acc, &(v, b)| {
+            let x = self.bdd.var(self.vars[v]);
+            let lit = if b { x } else { self.bdd.not(x) };
+            self.bdd.and(acc, lit)
+        })
+    }
+
+    fn cover_node(&mut self, cover: &[Cube]) -> Node {
+        cover.iter().fold(Node::FALSE, |acc, cube| {
+            let c = self.cube_node(cube);
+            self.bdd.or(acc, c)
+        })
+    }
+
+    /// Removes literals from each cube while it stays inside the on-set,
+    /// then drops the cubes contained in others.
+    fn expand(&mut self, cover: &mut Vec<Cube>, round: usize) {
+        cover.sort_by_key(Vec::len);
+
+        for cube in cover.iter_mut() {
+            let lits = cube.clone();
+            for k in 0..lits.len() {
+                let lit = lits[(k + round) % lits.len()];
+                let pos = cube.iter().position(|&x| x == lit).unwrap();
+                cube.remove(pos);
+                let c = self.cube_node(cube);
+                if self.bdd.and(c, self.off) != Node::FALSE {
+                    cube.insert(pos, lit);
+                }
+            }
+        }
+
+        let mut i = 0;
+        while i < cover.len() {
+            let contained = (0..cover.len()).any(|j| {
+                j != i && cover[j].iter().all(|lit| cover[i].contains(lit)) && (cover[j] != cover[i] || j < i)
+            });
+            if contained {
+                cover.remove(i);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    /// Removes cubes which are covered by the rest of the cover.
+    fn irredundant(&mut self, cover: &mut Vec<Cube>) {
+        cover.sort_by_key(|cube| Reverse(cube.len()));
+
+        let mut i = 0;
+        while i < cover.len() {
+            let cube = cover.remove(i);
+            let c = self.cube_node(&cube);
+            let rest = self.cover_node(cover);
+            let not_rest = self.bdd.not(rest);
+            if self.bdd.and(c, not_rest) != Node::FALSE {
+                cover.insert(i, cube);
+                i += 1;
+            }
+        }
+    }
+
+    /// Shrinks each cube to the smallest cube containing the on-set part that no other cube covers.
+    fn reduce(&mut self, cover: &mut Vec<Cube>) {
+        let mut i = 0;
+        while i < cover.len() {
+            let cube = cover.remove(i);
+            let rest = self.cover_node(cover);
+            let not_rest = self.bdd.not(rest);
+            let c = self.cube_node(&cube);
+            let c = self.bdd.and(c, self.on);
+            let g = self.bdd.and(c, not_rest);
+
+            if g == Node::FALSE {
+                continue;
+            }
+
+            let mut reduced = cube;
+            for v in 0..self.vars.len() {
+                if reduced.iter().any(|&(u, _)| u == v) {
+                    continue;
+                }
+                let x = self.bdd.var(self.vars[v]);
+                if self.bdd.and(g, x) == Node::FALSE {
+                    reduced.push((v, false));
+                } else {
+                    let not_x = self.bdd.not(x);
+                    if self.bdd.and(g, not_x) == Node::FALSE {
+                        reduced.push((v, true));
+                    }
+                }
+            }
+            reduced.sort_unstable();
+
+            cover.insert(i, reduced);
+            i += 1;
+        }
+        debug_assert!(self.cover_node(cover) == self.on);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+    use crate::eval::is_equivalent;
+
+    #[test]
+    fn exact() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        // consensus: any(all(x0, x1), all(not(x0), x2), all(x1, x2)) => any(all(x0, x1), all(not(x0), x2))
+        let src = expr(any((all((x0, x1)), all((not(x0), x2)), all((x1, x2)))));
+        let dst = minimize_sop(&src, Mode::Exact);
+        assert_eq!(dst.to_string(), "any(all(not(0), 2), all(0, 1))");
+        assert!(is_equivalent(&src, &dst).is_ok());
+
+        let dst = minimize_pos(&src, Mode::Exact);
+        assert_eq!(dst.to_string(), "all(any(0, 2), any(not(0), 1))");
+        assert!(is_equivalent(&src, &dst).is_ok());
+
+        assert_eq!(minimize_sop(&expr(any((x0, not(x0)))), Mode::Exact).to_string(), "true");
+        assert_eq!(
+            minimize_sop(&expr(all((x0, not(x0)))), Mode::Exact).to_string(),
+            "false"
+        );
+    }
+
+    #[test]
+    fn exact_fallback() {
+        // any(all(x0, x1), all(x2, x3), ...) over 24 variables
+        let terms: Vec<Expr<u32>> = (0..12).map(|i| expr(all((var(2 * i), var(2 * i + 1))))).collect();
+        let src = expr(any(terms));
+
+        let dst = minimize_sop(&src, Mode::Exact);
+        assert!(is_equivalent(&src, &dst).is_ok());
+        assert_eq!(dst, minimize_sop(&src, Mode::Heuristic));
+    }
+
+    #[test]
+    fn wide_tautology() {
+        // `any(0, ..., 15, not(0))` has `3^16` implicants, so it must not be merged exhaustively
+        let mut terms: Vec<Expr<u32>> = (0..16).map(|i| expr(var(i))).collect();
+        terms.push(expr(not(var(0))));
+        let src = expr(any(terms));
+
+        let start = std::time::Instant::now();
+        assert_eq!(minimize_sop(&src, Mode::Exact).to_string(), "true");
+        assert_eq!(minimize_pos(&src, Mode::Exact).to_string(), "true");
+        assert!(start.elapsed().as_secs() < 5);
+    }
+
+    #[test]
+    fn heuristic() {
+        let vars: Vec<Expr<u32>> = (0..16).map(|i| expr(var(i))).collect();
+
+        // any(all(x0, x1), all(x0, not(x1)), all(x2, x3), ...) => any(x0, all(x2, x3), ...)
+        let mut terms = Vec::new();
+        for i in (0..16).step_by(4) {
+            terms.push(expr(all((vars[i].clone(), vars[i + 1].clone()))));
+            terms.push(expr(all((vars[i].clone(), not(vars[i + 1].clone())))));
+            terms.push(expr(all((vars[i + 2].clone(), vars[i + 3].clone()))));
+        }
+        let src = expr(any(terms));
+
+        let dst = minimize_sop(&src, Mode::Heuristic);
+        assert!(is_equivalent(&src, &dst).is_ok());
+        assert_eq!(
+            dst.to_string(),
+            "any(0, all(2, 3), 4, all(6, 7), 8, all(10, 11), 12, all(14, 15))"
+        );
+    }
+}
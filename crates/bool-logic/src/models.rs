@@ -0,0 +1,177 @@
+use crate::ast::Expr;
+use crate::bdd::Bdd;
+use crate::eval::Assignment;
+use crate::sat::{Encoder, Lit};
+use crate::utils::collect_vars;
+
+use std::hash::Hash;
+
+/// An iterator over the satisfying assignments of an expression.
+///
+/// Each assignment is found by the SAT solver and then blocked,
+/// so the iterator never yields the same projection twice.
+pub struct Models<T> {
+    encoder: Encoder<T>,
+    vars: Vec<(T, Lit)>,
+    done: bool,
+}
+
+/// Returns an iterator over all satisfying assignments of the variables of `expr`.
+pub fn models<T>(expr: &Expr<T>) -> Models<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let vars: Vec<T> = collect_vars(expr).into_iter().cloned().collect();
+    projected_models(expr, &vars)
+}
+
+/// Returns an iterator over the assignments of `vars` which can be extended to satisfy `expr`.
+pub fn projected_models<T>(expr: &Expr<T>, vars: &[T]) -> Models<T>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut encoder = Encoder::new();
+    encoder.assert(expr);
+    let vars = vars.iter().map(|v| (v.clone(), encoder.var(v))).collect();
+    Models {
+        encoder,
+        vars,
+        done: false,
+    }
+}
+
+impl<T> Iterator for Models<T>
+where
+    T: Eq + Hash + Clone,
+{
+    type Item = Assignment<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let Some(model) = self.encoder.solve() else {
+            self.done = true;
+            return None;
+        };
+
+        let list: Vec<(T, bool)> = self
+            .vars
+            .iter()
+            .map(|(var, _)| (var.clone(), model.get(var).unwrap()))
+            .collect();
+
+        let block: Vec<Lit> = self
+            .vars
+            .iter()
+            .zip(&list)
+            .map(|((_, lit), (_, val))| if *val { !*lit } else { *lit })
+            .collect();
+        if block.is_empty() {
+            self.done = true;
+        } else {
+            self.encoder.add_clause(&block);
+        }
+
+        Some(Assignment(list))
+    }
+}
+
+/// Counts the satisfying assignments of the variables of `expr` without enumerating them.
+///
+/// Returns `None` if the count does not fit in `u128`, see [`Bdd::sat_count`].
+pub fn count_models<T>(expr: &Expr<T>) -> Option<u128>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut bdd = Bdd::new();
+    let f = bdd.from_expr(expr);
+    bdd.sat_count(f)
+}
+
+/// Counts the assignments of `vars` which can be extended to satisfy `expr`.
+///
+/// Returns `None` if the count does not fit in `u128`, see [`Bdd::sat_count`].
+pub fn count_projected_models<T>(expr: &Expr<T>, vars: &[T]) -> Option<u128>
+where
+    T: Eq + Hash + Clone,
+{
+    // `vars` come first in the order, so the hidden variables are the last ones.
+    let mut bdd = Bdd::new();
+    for var in vars {
+        bdd.var(var);
+    }
+    let shown = bdd.num_vars();
+    let f = bdd.from_expr(expr);
+
+    let hidden: Vec<T> = collect_vars(expr)
+        .into_iter()
+        .filter(|v| !vars.contains(v))
+        .cloned()
+        .collect();
+    let f = bdd.exists(f, &hidden);
+
+    bdd.sat_count_upto(f, shown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    #[test]
+    fn enumerate() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+        let src = expr(all((any((x0, x1)), not(all((x0, x1))), any((x2, not(x2))))));
+
+        let mut list: Vec<_> = models(&src).map(|m| m.0).collect();
+        list.sort();
+        assert_eq!(
+            list,
+            [
+                [(0, false), (1, true), (2, false)],
+                [(0, false), (1, true), (2, true)],
+                [(0, true), (1, false), (2, false)],
+                [(0, true), (1, false), (2, true)],
+            ]
+        );
+        assert_eq!(count_models(&src), Some(4));
+
+        let mut list: Vec<_> = projected_models(&src, &[1, 3]).map(|m| m.0).collect();
+        list.sort();
+        assert_eq!(list.len(), 4);
+        assert_eq!(list[0], [(1, false), (3, false)]);
+        assert_eq!(count_projected_models(&src, &[1, 3]), Some(4));
+        assert_eq!(count_projected_models(&src, &[0]), Some(2));
+    }
+
+    #[test]
+    fn projected_count() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        // the hidden `2` must be true, so `1` must be false and `0` must be true
+        let src = expr(all((any((x0, x1)), not(all((x1, x2))), x2)));
+        assert_eq!(count_models(&src), Some(1));
+        assert_eq!(count_projected_models(&src, &[0, 1]), Some(1));
+        assert_eq!(count_projected_models(&src, &[0, 1, 3]), Some(2));
+
+        // `all(0, 1, ..., 199)` with 199 hidden variables
+        let src = expr(all((0..200).map(|i| expr(var(i))).collect::<Vec<_>>()));
+        assert_eq!(count_models(&src), Some(1));
+        assert_eq!(count_projected_models(&src, &[0]), Some(1));
+        assert_eq!(count_projected_models(&src, &[200]), Some(2));
+    }
+
+    #[test]
+    fn trivial() {
+        let src: Expr<u32> = expr(all((var(0), not(var(0)))));
+        assert_eq!(models(&src).count(), 0);
+        assert_eq!(count_models(&src), Some(0));
+
+        let src: Expr<u32> = const_(true);
+        assert_eq!(models(&src).count(), 1);
+        assert_eq!(count_models(&src), Some(1));
+    }
+}
@@ -0,0 +1,413 @@
+use crate::ast::{self, Expr, Var};
+use crate::fold::try_postorder_into;
+use crate::visit::postorder_with;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A variable or its negation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Literal<T> {
+    pub var: T,
+    pub positive: bool,
+}
+
+/// A conjunction of clauses. Each clause is a disjunction of literals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cnf<T>(pub Vec<Vec<Literal<T>>>);
+
+/// A disjunction of cubes. Each cube is a conjunction of literals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dnf<T>(pub Vec<Vec<Literal<T>>>);
+
+/// A variable of a Tseitin encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TseitinVar<T> {
+    /// A variable of the input expression.
+    Var(T),
+    /// A fresh variable which stands for a sub-expression.
+    Fresh(usize),
+}
+
+/// The distributive conversion would produce more terms than the limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimitExceeded {
+    pub limit: usize,
+}
+
+impl fmt::Display for SizeLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "normal form exceeds the size limit of {} terms", self.limit)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SizeLimitExceeded {}
+
+impl<T> Literal<T> {
+    pub fn new(var: T, positive: bool) -> Self {
+        Self { var, positive }
+    }
+
+    fn negate(self) -> Self {
+        Self {
+            var: self.var,
+            positive: !self.positive,
+        }
+    }
+
+    fn into_expr(self) -> Expr<T> {
+        let x = ast::expr(ast::var(self.var));
+        if self.positive {
+            x
+        } else {
+            ast::expr(ast::not(x))
+        }
+    }
+}
+
+impl<T> Cnf<T> {
+    pub fn into_expr(self) -> Expr<T> {
+        let list = self.0.into_iter().map(|clause| lits_into_expr(clause, true));
+        list_into_expr(list.collect(), false)
+    }
+}
+
+impl<T> Dnf<T> {
+    pub fn into_expr(self) -> Expr<T> {
+        let list = self.0.into_iter().map(|cube| lits_into_expr(cube, false));
+        list_into_expr(list.collect(), true)
+    }
+}
+
+impl<T> From<Cnf<T>> for Expr<T> {
+    fn from(cnf: Cnf<T>) -> Self {
+        cnf.into_expr()
+    }
+}
+
+impl<T> From<Dnf<T>> for Expr<T> {
+    fn from(dnf: Dnf<T>) -> Self {
+        dnf.into_expr()
+    }
+}
+
+fn lits_into_expr<T>(lits: Vec<Literal<T>>, is_any: bool) -> Expr<T> {
+    list_into_expr(lits.into_iter().map(Literal::into_expr).collect(), is_any)
+}
+
+fn list_into_expr<T>(mut list: Vec<Expr<T>>, is_any: bool) -> Expr<T> {
+    match list.len() {
+        0 => Expr::Const(!is_any),
+        1 => list.pop().unwrap(),
+        _ if is_any => ast::expr(ast::any(list)),
+        _ => ast::expr(ast::all(list)),
+    }
+}
+
+/// Pushes every `not` down to the variables and folds the negated constants.
+pub fn nnf<T>(expr: Expr<T>) -> Expr<T> {
+    to_nnf(expr, true)
+}
+
+/// Converts `expr` under the polarity `positive` without recursion.
+fn to_nnf<T>(expr: Expr<T>, positive: bool) -> Expr<T> {
+    /// A list whose operands are being converted.
+    struct Frame<T> {
+        positive: bool,
+        is_any: bool,
+        rest: vec::IntoIter<Expr<T>>,
+        ans: Vec<Expr<T>>,
+    }
+
+    let mut stack: Vec<Frame<T>> = Vec::new();
+    let mut next = (expr, positive);
+    loop {
+        let (mut expr, mut positive) = next;
+        while let Expr::Not(not) = expr {
+            expr = not.into_inner();
+            positive = !positive;
+        }
+
+        let mut result = None;
+        let list = match expr {
+            Expr::Any(any) => Some((positive, any.into_inner())),
+            Expr::All(all) => Some((!positive, all.into_inner())),
+            Expr::Var(var) => {
+                result = Some(Literal::new(var.0, positive).into_expr());
+                None
+            }
+            Expr::Const(b) => {
+                result = Some(Expr::Const(b == positive));
+                None
+            }
+            Expr::Not(_) => unreachable!(),
+        };
+        if let Some((is_any, list)) = list {
+            stack.push(Frame {
+                positive,
+                is_any,
+                ans: Vec::with_capacity(list.len()),
+                rest: list.into_iter(),
+            });
+        }
+
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                return result.unwrap();
+            };
+            match result.take() {
+                Some(Expr::Any(inner)) if frame.is_any => frame.ans.extend(inner.into_inner()),
+                Some(Expr::All(inner)) if !frame.is_any => frame.ans.extend(inner.into_inner()),
+                Some(expr) => frame.ans.push(expr),
+                None => {}
+            }
+            if let Some(expr) = frame.rest.next() {
+                next = (expr, frame.positive);
+                break;
+            }
+            let Frame { is_any, ans, .. } = stack.pop().unwrap();
+            result = Some(if is_any {
+                ast::expr(ast::any(ans))
+            } else {
+                ast::expr(ast::all(ans))
+            });
+        }
+    }
+}
+
+/// Converts `expr` into CNF by distributing `any` over `all`.
+/// Fails if the result would contain more than `limit` clauses.
+pub fn cnf<T>(expr: Expr<T>, limit: usize) -> Result<Cnf<T>, SizeLimitExceeded>
+where
+    T: Eq + Clone,
+{
+    distribute(nnf(expr), true, limit).map(Cnf)
+}
+
+/// Converts `expr` into DNF by distributing `all` over `any`.
+/// Fails if the result would contain more than `limit` cubes.
+pub fn dnf<T>(expr: Expr<T>, limit: usize) -> Result<Dnf<T>, SizeLimitExceeded>
+where
+    T: Eq + Clone,
+{
+    distribute(nnf(expr), false, limit).map(Dnf)
+}
+
+/// Returns the terms of a CNF (`is_cnf`) or a DNF of an expression in NNF.
+///
+/// The outer list is the "product" of the normal form and the inner lists are the "sums".
+fn distribute<T>(expr: Expr<T>, is_cnf: bool, limit: usize) -> Result<Vec<Vec<Literal<T>>>, SizeLimitExceeded>
+where
+    T: Eq + Clone,
+{
+    try_postorder_into(expr, |expr, operands: Vec<Vec<Vec<Literal<T>>>>| {
+        let (product, sum) = if is_cnf {
+            (expr.is_all(), expr.is_any())
+        } else {
+            (expr.is_any(), expr.is_all())
+        };
+
+        match expr {
+            Expr::Any(_) | Expr::All(_) => {}
+            // the operand of `not` is a variable, whose terms are `[[var]]`
+            Expr::Not(_) => {
+                return match operands[0].as_slice() {
+                    [term] if matches!(term.as_slice(), [lit] if lit.positive) => {
+                        Ok(vec![vec![term[0].clone().negate()]])
+                    }
+                    _ => panic!("expected an expression in NNF"),
+                }
+            }
+            Expr::Var(Var(var)) => return Ok(vec![vec![Literal::new(var, true)]]),
+            // In CNF, `true` is the empty product and `false` is the product of an empty sum.
+            Expr::Const(b) => return Ok(if b == is_cnf { vec![] } else { vec![vec![]] }),
+        }
+
+        if product {
+            let mut ans = Vec::new();
+            for terms in operands {
+                ans.extend(terms);
+                if ans.len() > limit {
+                    return Err(SizeLimitExceeded { limit });
+                }
+            }
+            Ok(ans)
+        } else {
+            debug_assert!(sum);
+            product_of_sums(operands, limit)
+        }
+    })
+}
+
+/// Distributes a sum over the terms of its operands.
+fn product_of_sums<T>(
+    operands: Vec<Vec<Vec<Literal<T>>>>,
+    limit: usize,
+) -> Result<Vec<Vec<Literal<T>>>, SizeLimitExceeded>
+where
+    T: Eq + Clone,
+{
+    let mut ans: Vec<Vec<Literal<T>>> = vec![Vec::new()];
+    for terms in operands {
+        if ans.len().saturating_mul(terms.len()) > limit {
+            return Err(SizeLimitExceeded { limit });
+        }
+        let mut next = Vec::with_capacity(ans.len() * terms.len());
+        for lhs in &ans {
+            for rhs in &terms {
+                let mut term = lhs.clone();
+                for lit in rhs {
+                    if !term.contains(lit) {
+                        term.push(lit.clone());
+                    }
+                }
+                if !is_complementary(&term) {
+                    next.push(term);
+                }
+            }
+        }
+        ans = next;
+    }
+    Ok(ans)
+}
+
+/// Whether a term contains both `x` and `not(x)`.
+fn is_complementary<T: Eq + Clone>(term: &[Literal<T>]) -> bool {
+    term.iter().any(|lit| term.contains(&lit.clone().negate()))
+}
+
+/// Converts `expr` into an equisatisfiable CNF of linear size.
+///
+/// Every `any` and `all` is replaced by a fresh variable which is constrained to be equivalent to it.
+pub fn tseitin<T>(expr: &Expr<T>) -> Cnf<TseitinVar<T>>
+where
+    T: Clone,
+{
+    let mut encoder = Tseitin {
+        clauses: Vec::new(),
+        fresh: 0,
+    };
+    match encoder.encode(expr) {
+        Err(true) => {}
+        Err(false) => encoder.clauses.push(Vec::new()),
+        Ok(root) => encoder.clauses.push(vec![root]),
+    }
+    Cnf(encoder.clauses)
+}
+
+struct Tseitin<T> {
+    clauses: Vec<Vec<Literal<TseitinVar<T>>>>,
+    fresh: usize,
+}
+
+impl<T: Clone> Tseitin<T> {
+    /// Returns a literal equivalent to `expr`, or `Err` with the value of a constant expression.
+    fn encode(&mut self, expr: &Expr<T>) -> Result<Literal<TseitinVar<T>>, bool> {
+        postorder_with(expr, |expr, mut operands| match expr {
+            Expr::Any(_) => self.encode_gate(operands, true),
+            Expr::All(_) => self.encode_gate(operands, false),
+            Expr::Not(_) => operands.pop().unwrap().map(Literal::negate).map_err(|b| !b),
+            Expr::Var(Var(var)) => Ok(Literal::new(TseitinVar::Var(var.clone()), true)),
+            Expr::Const(b) => Err(*b),
+        })
+    }
+
+    fn encode_gate(
+        &mut self,
+        operands: Vec<Result<Literal<TseitinVar<T>>, bool>>,
+        is_any: bool,
+    ) -> Result<Literal<TseitinVar<T>>, bool> {
+        // `any` is absorbed by `true` and `all` is absorbed by `false`
+        let absorbing = is_any;
+
+        let mut lits = Vec::with_capacity(operands.len());
+        for operand in operands {
+            match operand {
+                Ok(lit) => lits.push(lit),
+                Err(b) if b == absorbing => return Err(absorbing),
+                Err(_) => {}
+            }
+        }
+
+        match lits.len() {
+            0 => return Err(!absorbing),
+            1 => return Ok(lits.pop().unwrap()),
+            _ => {}
+        }
+
+        let g = Literal::new(TseitinVar::Fresh(self.fresh), true);
+        self.fresh += 1;
+
+        // any: `g -> any(lits)` and `lit -> g`
+        // all: `not(g) -> any(not(lits))` and `not(lit) -> not(g)`
+        let sign = |lit: Literal<_>| if is_any { lit } else { lit.negate() };
+
+        let mut long = vec![sign(g.clone()).negate()];
+        for lit in lits {
+            self.clauses.push(vec![sign(g.clone()), sign(lit.clone()).negate()]);
+            long.push(sign(lit));
+        }
+        self.clauses.push(long);
+
+        Ok(g)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+    #[cfg(feature = "std")]
+    use crate::eval::{is_equivalent, is_satisfiable};
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn nnf_cnf_dnf() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        let src = expr(not(all((x0, any((x1, not(x2)))))));
+        assert_eq!(nnf(src.clone()).to_string(), "any(not(0), all(not(1), 2))");
+
+        let cnf = cnf(src.clone(), 16).unwrap();
+        assert_eq!(cnf.0.len(), 2);
+        let cnf = cnf.into_expr();
+        assert_eq!(cnf.to_string(), "all(any(not(0), not(1)), any(not(0), 2))");
+        assert!(is_equivalent(&src, &cnf).is_ok());
+
+        let dnf = dnf(src.clone(), 16).unwrap().into_expr();
+        assert_eq!(dnf.to_string(), "any(not(0), all(not(1), 2))");
+
+        let dnf = super::dnf(expr(all((x0, not(x0), x1))), 16).unwrap();
+        assert_eq!(dnf.into_expr().to_string(), "false");
+    }
+
+    #[test]
+    fn size_limit() {
+        // any(all(x0, x1), all(x2, x3), ...) has 2^n clauses in CNF
+        let list: Vec<Expr<u32>> = (0..10).map(|i| expr(all((var(2 * i), var(2 * i + 1))))).collect();
+        let src = expr(any(list));
+
+        assert_eq!(cnf(src.clone(), 1000), Err(SizeLimitExceeded { limit: 1000 }));
+        assert_eq!(cnf(src.clone(), 1024).unwrap().0.len(), 1024);
+        assert_eq!(dnf(src, 1000).unwrap().0.len(), 10);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn tseitin_encoding() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        let src = expr(any((all((x0, x1)), all((not(x0), x2)), const_(false))));
+        let cnf = tseitin(&src);
+        assert_eq!(cnf.0.len(), 10);
+        assert!(is_satisfiable(&cnf.into_expr()).is_ok());
+
+        let src = expr(all((x0, not(x0))));
+        assert!(is_satisfiable(&tseitin(&src).into_expr()).is_err());
+
+        assert_eq!(tseitin(&expr(any((x0, const_(true))))).0.len(), 0);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::ast::Expr;
+use crate::explain::Trace;
+use crate::visit_mut::VisitMut;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::any::type_name;
+use core::fmt;
+
+/// A sequence of [`VisitMut`] passes which is applied repeatedly until the expression
+/// stops changing or the round budget is exhausted.
+pub struct Pipeline<T> {
+    passes: Vec<Pass<T>>,
+    max_rounds: usize,
+}
+
+struct Pass<T> {
+    name: &'static str,
+    visitor: Box<dyn VisitMut<T>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineStats {
+    /// The number of rounds that have been run.
+    pub rounds: usize,
+    /// Whether the last round left the expression unchanged.
+    pub fixpoint: bool,
+    /// Statistics of each pass, in the order of the pipeline.
+    pub passes: Vec<PassStats>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassStats {
+    pub name: &'static str,
+    /// The number of times the pass has been run.
+    pub runs: usize,
+    /// The number of runs which changed the expression.
+    pub changes: usize,
+}
+
+impl<T> Pipeline<T> {
+    pub const DEFAULT_MAX_ROUNDS: usize = 16;
+
+    pub fn new() -> Self {
+        Self {
+            passes: Vec::new(),
+            max_rounds: Self::DEFAULT_MAX_ROUNDS,
+        }
+    }
+
+    /// Sets the maximum number of rounds. A round runs every pass once.
+    pub fn max_rounds(mut self, max_rounds: usize) -> Self {
+        self.max_rounds = max_rounds;
+        self
+    }
+
+    /// Appends a pass named after its type.
+    pub fn pass<V>(self, visitor: V) -> Self
+    where
+        V: VisitMut<T> + 'static,
+    {
+        let name = type_name::<V>();
+        let name = name.rsplit("::").next().unwrap_or(name);
+        self.named_pass(name, visitor)
+    }
+
+    /// Appends a pass with a custom name.
+    pub fn named_pass<V>(mut self, name: &'static str, visitor: V) -> Self
+    where
+        V: VisitMut<T> + 'static,
+    {
+        self.passes.push(Pass {
+            name,
+            visitor: Box::new(visitor),
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.passes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.passes.is_empty()
+    }
+}
+
+impl<T> Default for Pipeline<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> fmt::Debug for Pipeline<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.passes.iter().map(|p| p.name).collect();
+        f.debug_struct("Pipeline")
+            .field("passes", &names)
+            .field("max_rounds", &self.max_rounds)
+            .finish()
+    }
+}
+
+impl<T> Pipeline<T>
+where
+    T: Clone + Eq,
+{
+    pub fn run(&mut self, expr: &mut Expr<T>) -> PipelineStats {
+        self.run_impl(expr, None, |_, _| {})
+    }
+
+    /// Runs the pipeline and calls `inspect` with the pass name and the expression after each pass.
+    pub fn run_with<F>(&mut self, expr: &mut Expr<T>, inspect: F) -> PipelineStats
+    where
+        F: FnMut(&str, &Expr<T>),
+    {
+        self.run_impl(expr, None, inspect)
+    }
+
+    /// Runs the pipeline and records the subexpressions changed by each pass.
+    pub fn run_traced(&mut self, expr: &mut Expr<T>, trace: &mut Trace<T>) -> PipelineStats {
+        self.run_impl(expr, Some(trace), |_, _| {})
+    }
+
+    /// Each pass reports whether it has changed `expr` by [`VisitMut::visit_mut_changed`],
+    /// so `expr` is copied before a pass only when there is a trace to record.
+    fn run_impl<F>(&mut self, expr: &mut Expr<T>, mut trace: Option<&mut Trace<T>>, mut inspect: F) -> PipelineStats
+    where
+        F: FnMut(&str, &Expr<T>),
+    {
+        let mut stats = PipelineStats {
+            rounds: 0,
+            fixpoint: false,
+            passes: self
+                .passes
+                .iter()
+                .map(|p| PassStats {
+                    name: p.name,
+                    runs: 0,
+                    changes: 0,
+                })
+                .collect(),
+        };
+
+        while stats.rounds < self.max_rounds {
+            stats.rounds += 1;
+
+            let mut changed = false;
+            for (pass, pass_stats) in self.passes.iter_mut().zip(&mut stats.passes) {
+                let pass_changed = match trace.as_deref_mut() {
+                    Some(trace) => {
+                        let before = expr.clone();
+                        let pass_changed = pass.visitor.visit_mut_changed(expr);
+                        if pass_changed {
+                            trace.record(pass.name, &before, expr);
+                        }
+                        pass_changed
+                    }
+                    None => pass.visitor.visit_mut_changed(expr),
+                };
+                inspect(pass.name, expr);
+
+                pass_stats.runs += 1;
+                if pass_changed {
+                    pass_stats.changes += 1;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                stats.fixpoint = true;
+                break;
+            }
+        }
+
+        stats
+    }
+}
+
+impl PipelineStats {
+    /// Returns the passes which have changed the expression at least once.
+    pub fn fired(&self) -> impl Iterator<Item = &PassStats> {
+        self.passes.iter().filter(|p| p.changes > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+    use crate::transform::*;
+
+    #[test]
+    fn fixpoint() {
+        let mut x: Expr<u32> = expr(not(not(all((any((var(0),)), const_(true))))));
+
+        let mut pipeline = Pipeline::new().pass(FlattenSingle).pass(EvalConst);
+        let stats = pipeline.run(&mut x);
+
+        assert_eq!(x.to_string(), "0");
+        assert!(stats.fixpoint);
+        assert_eq!(stats.rounds, 3);
+        assert_eq!(stats.passes[0].name, "FlattenSingle");
+        assert_eq!(stats.passes[0].runs, 3);
+        assert!(stats.fired().count() > 0);
+    }
+
+    #[test]
+    fn budget() {
+        struct Flip;
+
+        impl VisitMut<u32> for Flip {
+            fn visit_mut_const(&mut self, b: &mut bool) {
+                *b = !*b;
+            }
+        }
+
+        let mut x: Expr<u32> = expr(all((var(0), const_(true))));
+
+        let mut pipeline = Pipeline::new().max_rounds(3).pass(Flip).pass(DedupList);
+        let stats = pipeline.run(&mut x);
+
+        assert!(!stats.fixpoint);
+        assert_eq!(stats.rounds, 3);
+        assert_eq!(stats.passes[0].changes, 3);
+        assert_eq!(stats.passes[1].changes, 0);
+        assert_eq!(x.to_string(), "all(0, false)");
+    }
+}
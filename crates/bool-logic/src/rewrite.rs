@@ -0,0 +1,516 @@
+use crate::ast::{self, All, Any, Expr, Not};
+use crate::explain::{Event, Trace};
+use crate::visit_mut::*;
+
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A pattern of expressions.
+///
+/// ```text
+/// pattern := "any(" items ")" | "all(" items ")" | "not(" pattern ")" | "true" | "false" | "?" name
+/// items   := [item ("," item)*]
+/// item    := pattern | ".." | ".." "?" name
+/// ```
+///
+/// The operands of `any` and `all` are matched in any order.
+/// A `..` matches the remaining operands, which can be bound to a name and spliced into another list.
+/// A metavariable occurring more than once matches equal expressions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    Any(Vec<Item>),
+    All(Vec<Item>),
+    Not(Box<Pattern>),
+    Const(bool),
+    Meta(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    One(Pattern),
+    Rest(Option<String>),
+}
+
+/// A rewrite rule `lhs => rhs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rewrite {
+    lhs: Pattern,
+    rhs: Pattern,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub pos: usize,
+    pub msg: &'static str,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.msg, self.pos)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+
+struct Parser<'s> {
+    src: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error<U>(&self, msg: &'static str) -> Result<U, ParseError> {
+        Err(ParseError { pos: self.pos, msg })
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = &self.src[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        if self.src[self.pos..].starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str, msg: &'static str) -> Result<(), ParseError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            self.error(msg)
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ParseError> {
+        let rest = &self.src[self.pos..];
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return self.error("expected a name");
+        }
+        self.pos += len;
+        Ok(rest[..len].to_owned())
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, ParseError> {
+        if self.eat("?") {
+            return Ok(Pattern::Meta(self.name()?));
+        }
+        if self.eat("true") {
+            return Ok(Pattern::Const(true));
+        }
+        if self.eat("false") {
+            return Ok(Pattern::Const(false));
+        }
+        if self.eat("not(") {
+            let not = self.pattern()?;
+            self.expect(")", "expected `)`")?;
+            return Ok(Pattern::Not(Box::new(not)));
+        }
+        if self.eat("any(") {
+            return Ok(Pattern::Any(self.items()?));
+        }
+        if self.eat("all(") {
+            return Ok(Pattern::All(self.items()?));
+        }
+        self.error("expected a pattern")
+    }
+
+    fn items(&mut self) -> Result<Vec<Item>, ParseError> {
+        let mut items = Vec::new();
+        if self.eat(")") {
+            return Ok(items);
+        }
+        loop {
+            if self.eat("..") {
+                if items.iter().any(|x| matches!(x, Item::Rest(_))) {
+                    return self.error("more than one `..` in a list");
+                }
+                let name = if self.eat("?") { Some(self.name()?) } else { None };
+                items.push(Item::Rest(name));
+            } else {
+                items.push(Item::One(self.pattern()?));
+            }
+            if self.eat(")") {
+                return Ok(items);
+            }
+            self.expect(",", "expected `,` or `)`")?;
+        }
+    }
+}
+
+impl Pattern {
+    pub fn parse(src: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser { src, pos: 0 };
+        let pattern = parser.pattern()?;
+        parser.skip_ws();
+        if parser.pos != src.len() {
+            return parser.error("unexpected trailing input");
+        }
+        Ok(pattern)
+    }
+
+    /// Calls `f` with each metavariable and whether it is a rest.
+    fn for_each_meta<'p>(&'p self, f: &mut impl FnMut(Option<&'p str>, bool)) {
+        let items = match self {
+            Pattern::Any(items) | Pattern::All(items) => items,
+            Pattern::Not(not) => return not.for_each_meta(f),
+            Pattern::Const(_) => return,
+            Pattern::Meta(name) => return f(Some(name), false),
+        };
+        for item in items {
+            match item {
+                Item::One(p) => p.for_each_meta(f),
+                Item::Rest(name) => f(name.as_deref(), true),
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (op, items) = match self {
+            Pattern::Any(items) => ("any", items),
+            Pattern::All(items) => ("all", items),
+            Pattern::Not(not) => return write!(f, "not({not})"),
+            Pattern::Const(b) => return write!(f, "{b}"),
+            Pattern::Meta(name) => return write!(f, "?{name}"),
+        };
+        write!(f, "{op}(")?;
+        for (i, item) in items.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            match item {
+                Item::One(p) => write!(f, "{p}")?,
+                Item::Rest(None) => write!(f, "..")?,
+                Item::Rest(Some(name)) => write!(f, "..?{name}")?,
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+/// The expressions bound to metavariables.
+struct Bindings<'p, 'e, T> {
+    one: Vec<(&'p str, &'e Expr<T>)>,
+    rest: Vec<(&'p str, Vec<&'e Expr<T>>)>,
+}
+
+impl<'p, 'e, T> Bindings<'p, 'e, T> {
+    fn mark(&self) -> (usize, usize) {
+        (self.one.len(), self.rest.len())
+    }
+
+    fn reset(&mut self, (one, rest): (usize, usize)) {
+        self.one.truncate(one);
+        self.rest.truncate(rest);
+    }
+
+    fn get(&self, name: &str) -> &'e Expr<T> {
+        self.one.iter().find(|(n, _)| *n == name).unwrap().1
+    }
+
+    fn get_rest(&self, name: &str) -> &[&'e Expr<T>] {
+        &self.rest.iter().find(|(n, _)| *n == name).unwrap().1
+    }
+}
+
+fn match_pattern<'p, 'e, T: Eq>(pattern: &'p Pattern, expr: &'e Expr<T>, b: &mut Bindings<'p, 'e, T>) -> bool {
+    match (pattern, expr) {
+        (Pattern::Any(items), Expr::Any(Any(list))) | (Pattern::All(items), Expr::All(All(list))) => {
+            let rest = items.iter().find_map(|x| match x {
+                Item::Rest(name) => Some(name.as_deref()),
+                Item::One(_) => None,
+            });
+            let mut used = vec![false; list.len()];
+            match_items(items, list, &mut used, rest, b)
+        }
+        (Pattern::Not(p), Expr::Not(Not(not))) => match_pattern(p, not, b),
+        (Pattern::Const(p), Expr::Const(x)) => p == x,
+        (Pattern::Meta(name), _) => match b.one.iter().find(|(n, _)| n == name) {
+            Some((_, bound)) => *bound == expr,
+            None => {
+                b.one.push((name, expr));
+                true
+            }
+        },
+        _ => false,
+    }
+}
+
+/// Matches the items to distinct operands. The unused operands are left for `rest`.
+fn match_items<'p, 'e, T: Eq>(
+    items: &'p [Item],
+    list: &'e [Expr<T>],
+    used: &mut [bool],
+    rest: Option<Option<&'p str>>,
+    b: &mut Bindings<'p, 'e, T>,
+) -> bool {
+    let Some((first, items)) = items.split_first() else {
+        return match rest {
+            None => used.iter().all(|&u| u),
+            Some(None) => true,
+            Some(Some(name)) => {
+                let unused = list.iter().zip(&*used).filter(|(_, &u)| !u).map(|(x, _)| x).collect();
+                b.rest.push((name, unused));
+                true
+            }
+        };
+    };
+
+    let Item::One(p) = first else {
+        return match_items(items, list, used, rest, b);
+    };
+    for i in 0..list.len() {
+        if used[i] {
+            continue;
+        }
+        let mark = b.mark();
+        if match_pattern(p, &list[i], b) {
+            used[i] = true;
+            if match_items(items, list, used, rest, b) {
+                return true;
+            }
+            used[i] = false;
+        }
+        b.reset(mark);
+    }
+    false
+}
+
+fn build<T: Clone>(pattern: &Pattern, b: &Bindings<'_, '_, T>) -> Expr<T> {
+    let build_items = |items: &[Item]| {
+        let mut list = Vec::new();
+        for item in items {
+            match item {
+                Item::One(p) => list.push(build(p, b)),
+                Item::Rest(name) => list.extend(b.get_rest(name.as_deref().unwrap()).iter().map(|&x| x.clone())),
+            }
+        }
+        list
+    };
+    match pattern {
+        Pattern::Any(items) => ast::expr(ast::any(build_items(items))),
+        Pattern::All(items) => ast::expr(ast::all(build_items(items))),
+        Pattern::Not(not) => ast::expr(ast::not(build(not, b))),
+        Pattern::Const(x) => Expr::Const(*x),
+        Pattern::Meta(name) => b.get(name).clone(),
+    }
+}
+
+impl Rewrite {
+    /// Parses a rule `lhs => rhs`.
+    ///
+    /// Every metavariable of `rhs` must be bound by `lhs`, and every `..` of `rhs` must be named.
+    pub fn parse(src: &str) -> Result<Self, ParseError> {
+        let Some(arrow) = src.find("=>") else {
+            return Err(ParseError {
+                pos: src.len(),
+                msg: "expected `=>`",
+            });
+        };
+        let lhs = Pattern::parse(&src[..arrow])?;
+        let rhs = Pattern::parse(&src[arrow + 2..]).map_err(|e| ParseError {
+            pos: e.pos + arrow + 2,
+            ..e
+        })?;
+
+        let mut bound = Vec::new();
+        let mut ok = true;
+        lhs.for_each_meta(&mut |name, is_rest| {
+            if let Some(name) = name {
+                ok &= !(is_rest && bound.contains(&(name, true)));
+                bound.push((name, is_rest));
+            }
+        });
+        if !ok {
+            return Err(ParseError {
+                pos: 0,
+                msg: "duplicate rest metavariable",
+            });
+        }
+        rhs.for_each_meta(&mut |name, is_rest| match name {
+            Some(name) => ok &= bound.contains(&(name, is_rest)),
+            None => ok = false,
+        });
+        if !ok {
+            return Err(ParseError {
+                pos: arrow + 2,
+                msg: "unbound metavariable",
+            });
+        }
+
+        Ok(Self { lhs, rhs })
+    }
+
+    pub fn lhs(&self) -> &Pattern {
+        &self.lhs
+    }
+
+    pub fn rhs(&self) -> &Pattern {
+        &self.rhs
+    }
+
+    /// Rewrites `expr` if its root matches `lhs`.
+    pub fn apply<T: Eq + Clone>(&self, expr: &Expr<T>) -> Option<Expr<T>> {
+        let mut b = Bindings {
+            one: Vec::new(),
+            rest: Vec::new(),
+        };
+        match_pattern(&self.lhs, expr, &mut b).then(|| build(&self.rhs, &b))
+    }
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} => {}", self.lhs, self.rhs)
+    }
+}
+
+/// Rewrites every subexpression bottom-up.
+impl Rewrite {
+    fn run<T: Eq + Clone>(&self, expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        postorder_mut(expr, |expr| {
+            if let Some(new) = self.apply(expr) {
+                changed |= new != *expr;
+                *expr = new;
+            }
+        });
+        changed
+    }
+}
+
+impl<T: Eq + Clone> VisitMut<T> for Rewrite {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        self.run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        self.run(expr)
+    }
+}
+
+/// A list of rewrite rules, tried in order at every subexpression bottom-up.
+#[derive(Debug, Clone, Default)]
+pub struct Rewrites {
+    rules: Vec<Rewrite>,
+}
+
+impl Rewrites {
+    pub fn new() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    /// Adds a rule `lhs => rhs`.
+    ///
+    /// # Panics
+    /// Panics if the rule is invalid. See [`Rewrite::parse`].
+    pub fn rule(mut self, src: &str) -> Self {
+        match Rewrite::parse(src) {
+            Ok(rule) => self.rules.push(rule),
+            Err(e) => panic!("invalid rule `{src}`: {e}"),
+        }
+        self
+    }
+
+    pub fn rules(&self) -> &[Rewrite] {
+        &self.rules
+    }
+}
+
+impl Rewrites {
+    /// Rewrites every subexpression bottom-up and records the rules which fired.
+    pub fn rewrite_traced<T: Eq + Clone>(&self, expr: &mut Expr<T>, trace: &mut Trace<T>) {
+        self.rewrite_at(expr, &mut Some(trace));
+    }
+
+    /// Returns whether `expr` has changed.
+    fn rewrite_at<T: Eq + Clone>(&self, expr: &mut Expr<T>, trace: &mut Option<&mut Trace<T>>) -> bool {
+        let mut changed = false;
+        traverse_mut(
+            expr,
+            |_| true,
+            |expr, _: Vec<()>, path| {
+                for rule in &self.rules {
+                    if let Some(new) = rule.apply(expr) {
+                        changed |= new != *expr;
+                        if let Some(trace) = trace {
+                            trace.push(Event {
+                                rule: rule.to_string(),
+                                path: path.to_vec(),
+                                before: expr.clone(),
+                                after: new.clone(),
+                            });
+                        }
+                        *expr = new;
+                    }
+                }
+            },
+        );
+        changed
+    }
+}
+
+impl<T: Eq + Clone> VisitMut<T> for Rewrites {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        self.rewrite_at(expr, &mut None);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        self.rewrite_at(expr, &mut None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    #[test]
+    fn parse() {
+        let rule = Rewrite::parse("any( ?x, all(?x, ..) ) => ?x").unwrap();
+        assert_eq!(rule.to_string(), "any(?x, all(?x, ..)) => ?x");
+
+        let err = |src| Rewrite::parse(src).unwrap_err().msg;
+        assert_eq!(err("any(?x) => ?y"), "unbound metavariable");
+        assert_eq!(err("any(?x, ..) => all(..)"), "unbound metavariable");
+        assert_eq!(err("any(?x, .., ..) => ?x"), "more than one `..` in a list");
+        assert_eq!(err("any(all(..?a), all(..?a)) => true"), "duplicate rest metavariable");
+        assert_eq!(err("any(?x ?y) => ?x"), "expected `,` or `)`");
+        assert_eq!(err("any(?x)"), "expected `=>`");
+    }
+
+    #[test]
+    fn rewrite() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+        let check = |rule: &str, mut src: Expr<u32>, expected: &str| {
+            Rewrites::new().rule(rule).visit_mut_expr(&mut src);
+            assert_eq!(src.to_string(), expected);
+        };
+
+        let absorb = "any(?x, all(?x, ..), ..?r) => any(?x, ..?r)";
+        check(absorb, expr(any((all((x1, x0)), x2, x0))), "any(0, 2)");
+        check(absorb, expr(any((all((x1, x0)), x2))), "any(all(1, 0), 2)");
+
+        let complement = "all(?x, not(?x), ..) => false";
+        check(complement, expr(any((x2, all((not(x0), x1, x0))))), "any(2, false)");
+
+        let distribute = "all(?x, any(?y, ?z)) => any(all(?x, ?y), all(?x, ?z))";
+        check(distribute, expr(all((x0, any((x1, x2))))), "any(all(0, 1), all(0, 2))");
+
+        check("not(not(?x)) => ?x", expr(not(not(not(not(x0))))), "0");
+    }
+}
@@ -0,0 +1,586 @@
+use crate::ast::{Expr, Var};
+use crate::eval::Assignment;
+use crate::visit::postorder_with;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem;
+use std::ops;
+
+/// A literal of a solver variable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Lit(u32);
+
+impl Lit {
+    pub fn new(var: u32, positive: bool) -> Self {
+        Self(var << 1 | u32::from(!positive))
+    }
+
+    pub fn pos(var: u32) -> Self {
+        Self::new(var, true)
+    }
+
+    pub fn neg(var: u32) -> Self {
+        Self::new(var, false)
+    }
+
+    pub fn var(self) -> u32 {
+        self.0 >> 1
+    }
+
+    pub fn is_positive(self) -> bool {
+        self.0 & 1 == 0
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl ops::Not for Lit {
+    type Output = Self;
+
+    fn not(self) -> Self::Output {
+        Self(self.0 ^ 1)
+    }
+}
+
+/// A CDCL SAT solver with two-watched-literal propagation, first-UIP clause learning,
+/// VSIDS-style branching, phase saving and restarts.
+#[derive(Debug, Default)]
+pub struct Solver {
+    clauses: Vec<Vec<Lit>>,
+    watches: Vec<Vec<usize>>,
+
+    assigns: Vec<Option<bool>>,
+    levels: Vec<usize>,
+    reasons: Vec<Option<usize>>,
+    activity: Vec<f64>,
+    phases: Vec<bool>,
+    seen: Vec<bool>,
+
+    trail: Vec<Lit>,
+    trail_lim: Vec<usize>,
+    qhead: usize,
+
+    var_inc: f64,
+    conflicts: u64,
+    unsat: bool,
+    model: Vec<bool>,
+}
+
+fn lit_value(assigns: &[Option<bool>], lit: Lit) -> Option<bool> {
+    assigns[lit.var() as usize].map(|val| val == lit.is_positive())
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Self {
+            var_inc: 1.0,
+            ..Self::default()
+        }
+    }
+
+    pub fn num_vars(&self) -> u32 {
+        self.assigns.len() as u32
+    }
+
+    /// The number of clauses in the database, including learnt clauses.
+    pub fn num_clauses(&self) -> usize {
+        self.clauses.len()
+    }
+
+    pub fn num_conflicts(&self) -> u64 {
+        self.conflicts
+    }
+
+    pub fn new_var(&mut self) -> u32 {
+        let var = self.num_vars();
+        self.assigns.push(None);
+        self.levels.push(0);
+        self.reasons.push(None);
+        self.activity.push(0.0);
+        self.phases.push(false);
+        self.seen.push(false);
+        self.watches.push(Vec::new());
+        self.watches.push(Vec::new());
+        var
+    }
+
+    /// Adds a clause. Returns `false` if the solver has become trivially unsatisfiable.
+    pub fn add_clause(&mut self, lits: &[Lit]) -> bool {
+        if self.unsat {
+            return false;
+        }
+        self.cancel_until(0);
+
+        let mut clause = lits.to_vec();
+        clause.sort_unstable();
+        clause.dedup();
+
+        for pair in clause.windows(2) {
+            if pair[0] == !pair[1] {
+                return true;
+            }
+        }
+
+        if clause.iter().any(|&lit| lit_value(&self.assigns, lit) == Some(true)) {
+            return true;
+        }
+        clause.retain(|&lit| lit_value(&self.assigns, lit).is_none());
+
+        match clause.len() {
+            0 => self.unsat = true,
+            1 => {
+                self.enqueue(clause[0], None);
+                if self.propagate().is_some() {
+                    self.unsat = true;
+                }
+            }
+            _ => {
+                self.attach(clause);
+            }
+        }
+
+        !self.unsat
+    }
+
+    /// Searches for a satisfying assignment.
+    pub fn solve(&mut self) -> bool {
+        if self.unsat {
+            return false;
+        }
+
+        let mut restart_limit = 100.0;
+        let mut conflicts_since_restart = 0;
+
+        loop {
+            if let Some(confl) = self.propagate() {
+                self.conflicts += 1;
+                conflicts_since_restart += 1;
+
+                if self.decision_level() == 0 {
+                    self.unsat = true;
+                    return false;
+                }
+
+                let (learnt, level) = self.analyze(confl);
+                self.cancel_until(level);
+
+                if learnt.len() == 1 {
+                    self.enqueue(learnt[0], None);
+                } else {
+                    let asserting = learnt[0];
+                    let ci = self.attach(learnt);
+                    self.enqueue(asserting, Some(ci));
+                }
+
+                self.decay_activity();
+                continue;
+            }
+
+            if conflicts_since_restart as f64 >= restart_limit {
+                conflicts_since_restart = 0;
+                restart_limit *= 1.5;
+                self.cancel_until(0);
+                continue;
+            }
+
+            match self.pick_branch_var() {
+                Some(var) => {
+                    self.trail_lim.push(self.trail.len());
+                    let lit = Lit::new(var, self.phases[var as usize]);
+                    self.enqueue(lit, None);
+                }
+                None => {
+                    self.model = self.assigns.iter().map(|val| val.unwrap_or(false)).collect();
+                    self.cancel_until(0);
+                    return true;
+                }
+            }
+        }
+    }
+
+    /// Returns the value of `var` in the model found by the last successful [`Solver::solve`].
+    pub fn model_value(&self, var: u32) -> Option<bool> {
+        self.model.get(var as usize).copied()
+    }
+
+    fn decision_level(&self) -> usize {
+        self.trail_lim.len()
+    }
+
+    fn attach(&mut self, clause: Vec<Lit>) -> usize {
+        let ci = self.clauses.len();
+        self.watches[clause[0].index()].push(ci);
+        self.watches[clause[1].index()].push(ci);
+        self.clauses.push(clause);
+        ci
+    }
+
+    fn enqueue(&mut self, lit: Lit, reason: Option<usize>) {
+        let var = lit.var() as usize;
+        self.assigns[var] = Some(lit.is_positive());
+        self.levels[var] = self.decision_level();
+        self.reasons[var] = reason;
+        self.trail.push(lit);
+    }
+
+    fn cancel_until(&mut self, level: usize) {
+        if self.decision_level() <= level {
+            return;
+        }
+
+        let start = self.trail_lim[level];
+        for lit in self.trail.drain(start..) {
+            let var = lit.var() as usize;
+            self.phases[var] = lit.is_positive();
+            self.assigns[var] = None;
+            self.reasons[var] = None;
+        }
+        self.trail_lim.truncate(level);
+        self.qhead = self.trail.len();
+    }
+
+    /// Propagates all enqueued assignments. Returns the conflicting clause if there is one.
+    fn propagate(&mut self) -> Option<usize> {
+        while self.qhead < self.trail.len() {
+            let false_lit = !self.trail[self.qhead];
+            self.qhead += 1;
+
+            let mut watchers = mem::take(&mut self.watches[false_lit.index()]);
+            let mut conflict = None;
+
+            let mut i = 0;
+            let mut j = 0;
+            while i < watchers.len() {
+                let ci = watchers[i];
+                i += 1;
+
+                let clause = &mut self.clauses[ci];
+                if clause[0] == false_lit {
+                    clause.swap(0, 1);
+                }
+
+                let first = clause[0];
+                if lit_value(&self.assigns, first) == Some(true) {
+                    watchers[j] = ci;
+                    j += 1;
+                    continue;
+                }
+
+                let new_watch = (2..clause.len()).find(|&k| lit_value(&self.assigns, clause[k]) != Some(false));
+                if let Some(k) = new_watch {
+                    clause.swap(1, k);
+                    self.watches[clause[1].index()].push(ci);
+                    continue;
+                }
+
+                watchers[j] = ci;
+                j += 1;
+
+                if lit_value(&self.assigns, first) == Some(false) {
+                    conflict = Some(ci);
+                    while i < watchers.len() {
+                        watchers[j] = watchers[i];
+                        i += 1;
+                        j += 1;
+                    }
+                } else {
+                    self.enqueue(first, Some(ci));
+                }
+            }
+
+            watchers.truncate(j);
+            self.watches[false_lit.index()] = watchers;
+
+            if conflict.is_some() {
+                self.qhead = self.trail.len();
+                return conflict;
+            }
+        }
+        None
+    }
+
+    /// Derives a first-UIP clause from the conflict. The asserting literal is placed first
+    /// and a literal of the backjump level second.
+    fn analyze(&mut self, mut confl: usize) -> (Vec<Lit>, usize) {
+        let mut learnt = vec![Lit(0)];
+        let mut pending = 0;
+        let mut index = self.trail.len();
+        let mut p: Option<Lit> = None;
+
+        loop {
+            let skip = usize::from(p.is_some());
+            for k in skip..self.clauses[confl].len() {
+                let q = self.clauses[confl][k];
+                let var = q.var() as usize;
+                if self.seen[var] || self.levels[var] == 0 {
+                    continue;
+                }
+                self.seen[var] = true;
+                self.bump_activity(var);
+                if self.levels[var] >= self.decision_level() {
+                    pending += 1;
+                } else {
+                    learnt.push(q);
+                }
+            }
+
+            loop {
+                index -= 1;
+                if self.seen[self.trail[index].var() as usize] {
+                    break;
+                }
+            }
+
+            let lit = self.trail[index];
+            let var = lit.var() as usize;
+            self.seen[var] = false;
+            pending -= 1;
+            p = Some(lit);
+
+            if pending == 0 {
+                break;
+            }
+            confl = self.reasons[var].unwrap();
+        }
+
+        learnt[0] = !p.unwrap();
+
+        for lit in &learnt[1..] {
+            self.seen[lit.var() as usize] = false;
+        }
+
+        let mut level = 0;
+        if learnt.len() > 1 {
+            let mut max_k = 1;
+            for k in 2..learnt.len() {
+                if self.levels[learnt[k].var() as usize] > self.levels[learnt[max_k].var() as usize] {
+                    max_k = k;
+                }
+            }
+            learnt.swap(1, max_k);
+            level = self.levels[learnt[1].var() as usize];
+        }
+
+        (learnt, level)
+    }
+
+    fn pick_branch_var(&self) -> Option<u32> {
+        let mut best: Option<usize> = None;
+        for (var, val) in self.assigns.iter().enumerate() {
+            if val.is_some() {
+                continue;
+            }
+            match best {
+                Some(b) if self.activity[b] >= self.activity[var] => {}
+                _ => best = Some(var),
+            }
+        }
+        best.map(|var| var as u32)
+    }
+
+    fn bump_activity(&mut self, var: usize) {
+        self.activity[var] += self.var_inc;
+        if self.activity[var] > 1e100 {
+            self.activity.iter_mut().for_each(|a| *a *= 1e-100);
+            self.var_inc *= 1e-100;
+        }
+    }
+
+    fn decay_activity(&mut self) {
+        self.var_inc /= 0.95;
+    }
+}
+
+/// Encodes expressions into a [`Solver`] by Tseitin transformation.
+#[derive(Debug)]
+pub struct Encoder<T> {
+    solver: Solver,
+    vars: HashMap<T, u32>,
+    order: Vec<T>,
+    const_true: Option<Lit>,
+}
+
+impl<T> Encoder<T>
+where
+    T: Eq + Hash + Clone,
+{
+    pub fn new() -> Self {
+        Self {
+            solver: Solver::new(),
+            vars: HashMap::new(),
+            order: Vec::new(),
+            const_true: None,
+        }
+    }
+
+    pub fn solver(&mut self) -> &mut Solver {
+        &mut self.solver
+    }
+
+    /// Returns the literal of a variable, allocating a solver variable on first use.
+    pub fn var(&mut self, var: &T) -> Lit {
+        if let Some(&v) = self.vars.get(var) {
+            return Lit::pos(v);
+        }
+        let v = self.solver.new_var();
+        self.vars.insert(var.clone(), v);
+        self.order.push(var.clone());
+        Lit::pos(v)
+    }
+
+    /// Returns a literal which is equivalent to `expr`.
+    pub fn encode(&mut self, expr: &Expr<T>) -> Lit {
+        postorder_with(expr, |expr, lits| match expr {
+            Expr::Any(_) => self.encode_gate(lits, false),
+            Expr::All(_) => self.encode_gate(lits, true),
+            Expr::Not(_) => !lits[0],
+            Expr::Var(Var(var)) => self.var(var),
+            Expr::Const(b) => {
+                let lit = self.const_true();
+                if *b {
+                    lit
+                } else {
+                    !lit
+                }
+            }
+        })
+    }
+
+    /// Adds the clauses of `g <-> and(lits)` or `g <-> or(lits)`.
+    fn encode_gate(&mut self, lits: Vec<Lit>, is_and: bool) -> Lit {
+        match lits.as_slice() {
+            [] => {
+                let lit = self.const_true();
+                return if is_and { lit } else { !lit };
+            }
+            [lit] => return *lit,
+            _ => {}
+        }
+
+        // `and(xs) = not(or(not(xs)))`
+        let sign = |lit: Lit| if is_and { !lit } else { lit };
+
+        let g = Lit::pos(self.solver.new_var());
+        let mut long = Vec::with_capacity(lits.len() + 1);
+        long.push(!sign(g));
+        for &lit in &lits {
+            self.solver.add_clause(&[sign(g), !sign(lit)]);
+            long.push(sign(lit));
+        }
+        self.solver.add_clause(&long);
+        g
+    }
+
+    fn const_true(&mut self) -> Lit {
+        if let Some(lit) = self.const_true {
+            return lit;
+        }
+        let lit = Lit::pos(self.solver.new_var());
+        self.solver.add_clause(&[lit]);
+        self.const_true = Some(lit);
+        lit
+    }
+
+    pub fn add_clause(&mut self, lits: &[Lit]) {
+        self.solver.add_clause(lits);
+    }
+
+    /// Asserts that `expr` is true.
+    pub fn assert(&mut self, expr: &Expr<T>) {
+        let lit = self.encode(expr);
+        self.solver.add_clause(&[lit]);
+    }
+
+    /// Returns an assignment of the encoded variables which satisfies all assertions.
+    pub fn solve(&mut self) -> Option<Assignment<T>> {
+        if !self.solver.solve() {
+            return None;
+        }
+        let list = self.order.iter().map(|var| {
+            let val = self.solver.model_value(self.vars[var]).unwrap();
+            (var.clone(), val)
+        });
+        Some(Assignment(list.collect()))
+    }
+}
+
+impl<T> Default for Encoder<T>
+where
+    T: Eq + Hash + Clone,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns a satisfying assignment of `expr` if there is one.
+pub fn solve<T>(expr: &Expr<T>) -> Option<Assignment<T>>
+where
+    T: Eq + Hash + Clone,
+{
+    let mut encoder = Encoder::new();
+    encoder.assert(expr);
+    encoder.solve()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    /// `n + 1` pigeons do not fit in `n` holes.
+    fn pigeonhole(n: u32) -> Expr<(u32, u32)> {
+        let mut list = Vec::new();
+        for p in 0..=n {
+            list.push(expr(any(map_holes(n, |h| expr(var((p, h)))))));
+        }
+        for h in 0..n {
+            for p in 0..=n {
+                for q in (p + 1)..=n {
+                    list.push(expr(not(all((var((p, h)), var((q, h)))))));
+                }
+            }
+        }
+        expr(all(list))
+    }
+
+    fn map_holes(n: u32, f: impl Fn(u32) -> Expr<(u32, u32)>) -> Vec<Expr<(u32, u32)>> {
+        (0..n).map(f).collect()
+    }
+
+    #[test]
+    fn unsat() {
+        assert!(solve(&pigeonhole(5)).is_none());
+
+        let mut encoder = Encoder::new();
+        encoder.assert(&pigeonhole(5));
+        assert!(encoder.solve().is_none());
+        assert!(encoder.solver().num_conflicts() > 0);
+    }
+
+    #[test]
+    fn sat() {
+        let x = pigeonhole(4);
+        let x = expr(any((x, all((var((9, 9)), not(var((9, 8))))))));
+
+        let model = solve(&x).unwrap();
+        assert!(model.eval(&x));
+        assert_eq!(model.get(&(9, 9)), Some(true));
+        assert_eq!(model.get(&(9, 8)), Some(false));
+    }
+
+    #[test]
+    fn many_vars() {
+        // x0 -> x1 -> ... -> x199, x0, not(x199)
+        let mut list: Vec<Expr<u32>> = (0..199).map(|i| expr(any((not(var(i)), var(i + 1))))).collect();
+        list.push(expr(var(0)));
+        assert!(solve(&expr(all(list.clone()))).is_some());
+
+        list.push(expr(not(var(199))));
+        assert!(solve(&expr(all(list))).is_none());
+    }
+}
@@ -0,0 +1,336 @@
+//! Property-based checks of semantic preservation.
+
+use crate::ast::{self, All, Any, Expr, Not, Var};
+use crate::eval::eval_with;
+use crate::visit_mut::VisitMut;
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+/// A xorshift pseudo-random number generator.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// A generator of random expressions over a pool of variables.
+#[derive(Debug, Clone)]
+pub struct Generator<T> {
+    vars: Vec<T>,
+    max_depth: usize,
+    max_width: usize,
+    consts: bool,
+}
+
+impl<T: Clone> Generator<T> {
+    pub fn new(vars: impl IntoIterator<Item = T>) -> Self {
+        Self {
+            vars: vars.into_iter().collect(),
+            max_depth: 4,
+            max_width: 4,
+            consts: false,
+        }
+    }
+
+    pub fn vars(&self) -> &[T] {
+        &self.vars
+    }
+
+    pub fn max_depth(mut self, n: usize) -> Self {
+        self.max_depth = n;
+        self
+    }
+
+    /// Sets the maximum number of operands of `any` and `all`.
+    pub fn max_width(mut self, n: usize) -> Self {
+        self.max_width = n;
+        self
+    }
+
+    /// Allows constants in generated expressions.
+    pub fn consts(mut self, enable: bool) -> Self {
+        self.consts = enable;
+        self
+    }
+
+    /// # Panics
+    /// Panics if the pool of variables is empty.
+    pub fn generate(&self, rng: &mut Rng) -> Expr<T> {
+        assert!(!self.vars.is_empty(), "no variables to generate");
+        self.generate_at(rng, self.max_depth)
+    }
+
+    fn generate_at(&self, rng: &mut Rng, depth: usize) -> Expr<T> {
+        let leaf = depth == 0 || rng.below(3) == 0;
+        if leaf {
+            if self.consts && rng.below(8) == 0 {
+                return Expr::Const(rng.below(2) == 0);
+            }
+            let var = self.vars[rng.below(self.vars.len())].clone();
+            return ast::expr(ast::var(var));
+        }
+        match rng.below(3) {
+            0 => ast::expr(ast::not(self.generate_at(rng, depth - 1))),
+            k => {
+                let width = rng.below(self.max_width.max(1)) + 1;
+                let list: Vec<Expr<T>> = (0..width).map(|_| self.generate_at(rng, depth - 1)).collect();
+                if k == 1 {
+                    ast::expr(ast::any(list))
+                } else {
+                    ast::expr(ast::all(list))
+                }
+            }
+        }
+    }
+}
+
+/// An input on which a pass changed the meaning of the expression, or panicked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Counterexample<T> {
+    pub input: Expr<T>,
+    /// `None` if the pass panicked.
+    pub output: Option<Expr<T>>,
+    /// An assignment under which `input` and `output` differ.
+    pub assignment: Vec<(T, bool)>,
+}
+
+impl<T: fmt::Display> fmt::Display for Counterexample<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "input:  {}", self.input)?;
+        match &self.output {
+            Some(output) => writeln!(f, "output: {output}")?,
+            None => writeln!(f, "output: <panicked>")?,
+        }
+        write!(f, "assignment:")?;
+        for (var, val) in &self.assignment {
+            write!(f, " {var}={val}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs a pass on random expressions and compares the truth tables of inputs and outputs.
+///
+/// Counterexamples are minimized by repeatedly replacing subexpressions
+/// with their operands or constants while the pass still fails.
+#[derive(Debug, Clone)]
+pub struct Checker<T> {
+    generator: Generator<T>,
+    assumption: Option<Expr<T>>,
+    cases: usize,
+    seed: u64,
+}
+
+impl<T> Checker<T>
+where
+    T: Eq + Clone,
+{
+    /// # Panics
+    /// Panics if the generator has more than 16 variables.
+    pub fn new(generator: Generator<T>) -> Self {
+        assert!(generator.vars.len() <= 16, "too many variables for truth tables");
+        Self {
+            generator,
+            assumption: None,
+            cases: 1000,
+            seed: 0x5eed,
+        }
+    }
+
+    pub fn cases(mut self, n: usize) -> Self {
+        self.cases = n;
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Only compares the assignments satisfying `assumption`.
+    pub fn assuming(mut self, assumption: Expr<T>) -> Self {
+        self.assumption = Some(assumption);
+        self
+    }
+
+    pub fn check<V: VisitMut<T>>(&self, pass: &mut V) -> Result<(), Counterexample<T>> {
+        let mut rng = Rng::new(self.seed);
+        for _ in 0..self.cases {
+            let input = self.generator.generate(&mut rng);
+            if let Some(cex) = self.run(pass, &input) {
+                return Err(self.minimize(pass, cex));
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the pass on a single input.
+    pub fn run<V: VisitMut<T>>(&self, pass: &mut V, input: &Expr<T>) -> Option<Counterexample<T>> {
+        let mut output = input.clone();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| pass.visit_mut_expr(&mut output)));
+        if result.is_err() {
+            return Some(Counterexample {
+                input: input.clone(),
+                output: None,
+                assignment: Vec::new(),
+            });
+        }
+
+        let vars = &self.generator.vars;
+        for mask in 0..1u32 << vars.len() {
+            let value = |var: &T| match vars.iter().position(|v| v == var) {
+                Some(i) => mask & (1 << i) != 0,
+                None => false,
+            };
+            if matches!(&self.assumption, Some(a) if !eval_with(a, &value)) {
+                continue;
+            }
+            if eval_with(input, &value) != eval_with(&output, &value) {
+                let assignment = vars.iter().map(|v| (v.clone(), value(v))).collect();
+                return Some(Counterexample {
+                    input: input.clone(),
+                    output: Some(output),
+                    assignment,
+                });
+            }
+        }
+        None
+    }
+
+    fn minimize<V: VisitMut<T>>(&self, pass: &mut V, mut cex: Counterexample<T>) -> Counterexample<T> {
+        'outer: loop {
+            for candidate in shrink(&cex.input) {
+                if let Some(smaller) = self.run(pass, &candidate) {
+                    cex = smaller;
+                    continue 'outer;
+                }
+            }
+            return cex;
+        }
+    }
+}
+
+/// Returns the expressions obtained by one shrinking step, largest reductions first.
+fn shrink<T: Clone>(expr: &Expr<T>) -> Vec<Expr<T>> {
+    let mut ans = Vec::new();
+    match expr {
+        Expr::Any(Any(list)) | Expr::All(All(list)) => {
+            ans.extend(list.iter().cloned());
+            for i in 0..list.len() {
+                let mut list = list.clone();
+                list.remove(i);
+                ans.push(rebuild(expr, list));
+            }
+            for (i, x) in list.iter().enumerate() {
+                for x in shrink(x) {
+                    let mut list = list.clone();
+                    list[i] = x;
+                    ans.push(rebuild(expr, list));
+                }
+            }
+        }
+        Expr::Not(Not(not)) => {
+            ans.push((**not).clone());
+            ans.extend(shrink(not).into_iter().map(|x| ast::expr(ast::not(x))));
+        }
+        Expr::Var(Var(_)) => ans.extend([Expr::Const(false), Expr::Const(true)]),
+        Expr::Const(_) => {}
+    }
+    ans
+}
+
+fn rebuild<T>(expr: &Expr<T>, list: Vec<Expr<T>>) -> Expr<T> {
+    match expr {
+        Expr::Any(_) => ast::expr(ast::any(list)),
+        _ => ast::expr(ast::all(list)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::arena::*;
+    use crate::transform::*;
+    use crate::visit_mut::*;
+
+    #[test]
+    fn sound_passes() {
+        let checker = Checker::new(Generator::new(0..4).consts(true)).cases(500);
+        checker.check(&mut FlattenSingle).unwrap();
+        checker.check(&mut FlattenNestedList).unwrap();
+        checker.check(&mut DedupList).unwrap();
+        checker.check(&mut EvalConst).unwrap();
+        checker.check(&mut SimplifyNestedList).unwrap();
+        checker.check(&mut SimplifyAllNotAny).unwrap();
+        checker.check(&mut MergeAllOfNotAny).unwrap();
+        checker.check(&mut FlattenByDeMorgan).unwrap();
+        checker.check(&mut MergeAllOfAny).unwrap();
+        checker.check(&mut SimplifyByShortCircuit).unwrap();
+        checker.check(&mut Factor).unwrap();
+    }
+
+    #[test]
+    fn change_reports() {
+        fn check(pass: &mut impl VisitMut<u32>) {
+            let generator = Generator::new(0..4).consts(true);
+            let mut rng = Rng::new(1);
+            for _ in 0..500 {
+                let x = generator.generate(&mut rng);
+                let mut y = x.clone();
+                assert_eq!(pass.visit_mut_changed(&mut y), y != x, "{x}");
+            }
+        }
+
+        check(&mut FlattenSingle);
+        check(&mut FlattenNestedList);
+        check(&mut DedupList);
+        check(&mut EvalConst);
+        check(&mut SimplifyNestedList);
+        check(&mut SimplifyAllNotAny);
+        check(&mut FlattenByDeMorgan);
+        check(&mut MergeAllOfNotAny);
+        check(&mut MergeAllOfAny);
+        check(&mut SimplifyByShortCircuit);
+        check(&mut Factor);
+        check(&mut DedupListByArena);
+        check(&mut SimplifyNestedListByArena);
+        check(&mut MergeAllOfAnyByArena);
+    }
+
+    #[test]
+    fn minimize() {
+        /// Wrongly drops the first operand of every `any`.
+        struct DropFirst;
+
+        impl VisitMut<u32> for DropFirst {
+            fn visit_mut_any(&mut self, Any(any): &mut Any<u32>) {
+                if any.len() > 1 {
+                    any.remove(0);
+                }
+                walk_mut_expr_list(self, any);
+            }
+        }
+
+        let checker = Checker::new(Generator::new(0..4));
+        let cex = checker.check(&mut DropFirst).unwrap_err();
+        assert_eq!(cex.input.to_string(), "any(true, false)");
+        assert_eq!(cex.output.unwrap().to_string(), "any(false)");
+    }
+}
@@ -1,9 +1,13 @@
 use crate::ast::{self, All, Any, Expr, Not, Var};
 use crate::utils::*;
+use crate::visit::preorder;
 use crate::visit_mut::*;
 
-use std::ops::Not as _;
-use std::slice;
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Not as _;
+use core::slice;
 
 use replace_with::replace_with_or_abort as replace_with;
 use rust_utils::default::default;
@@ -12,8 +16,8 @@ use rust_utils::slice::SliceExt;
 use rust_utils::vec::VecExt;
 
 fn unwrap_not<T>(expr: Expr<T>) -> Expr<T> {
-    if let Expr::Not(Not(not)) = expr {
-        *not
+    if let Expr::Not(not) = expr {
+        not.into_inner()
     } else {
         panic!()
     }
@@ -21,16 +25,16 @@ fn unwrap_not<T>(expr: Expr<T>) -> Expr<T> {
 
 pub struct FlattenSingle;
 
-impl<T> VisitMut<T> for FlattenSingle {
-    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
-        walk_mut_expr(self, expr);
-
+impl FlattenSingle {
+    fn flatten<T>(expr: &mut Expr<T>) -> bool {
         match expr {
             Expr::Any(Any(any)) => {
                 if any.is_empty() {
                     *expr = Expr::Const(false);
                 } else if any.len() == 1 {
                     *expr = any.pop().unwrap();
+                } else {
+                    return false;
                 }
             }
             Expr::All(All(all)) => {
@@ -38,99 +42,150 @@ impl<T> VisitMut<T> for FlattenSingle {
                     *expr = Expr::Const(true);
                 } else if all.len() == 1 {
                     *expr = all.pop().unwrap();
+                } else {
+                    return false;
                 }
             }
-            Expr::Not(Not(not_expr)) => {
-                if not_expr.is_not() {
-                    replace_with(expr, |expr| unwrap_not(unwrap_not(expr)))
-                }
-            }
-            _ => {}
+            Expr::Not(Not(not_expr)) if not_expr.is_not() => replace_with(expr, |expr| unwrap_not(unwrap_not(expr))),
+            _ => return false,
         };
+        true
+    }
+
+    /// The operands are flattened first, so the replacement of a subexpression is flattened already.
+    pub(crate) fn run<T>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        postorder_mut(expr, |expr| changed |= Self::flatten(expr));
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for FlattenSingle {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
 
-        walk_mut_expr(self, expr)
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
 pub struct FlattenNestedList;
 
 impl FlattenNestedList {
-    fn flatten_any<T>(list: &mut Vec<Expr<T>>) {
+    fn flatten_any<T>(list: &mut Vec<Expr<T>>) -> bool {
         if list.iter().all(|x| x.is_any().not()) {
-            return;
+            return false;
         }
 
         let mut ans: Vec<Expr<T>> = Vec::with_capacity(list.len());
         for expr in list.drain(..) {
-            if let Expr::Any(Any(any)) = expr {
-                ans.extend(any);
+            if let Expr::Any(any) = expr {
+                ans.extend(any.into_inner());
             } else {
                 ans.push(expr);
             }
         }
         *list = ans;
+        true
     }
 
-    fn flatten_all<T>(list: &mut Vec<Expr<T>>) {
+    fn flatten_all<T>(list: &mut Vec<Expr<T>>) -> bool {
         if list.iter().all(|x| x.is_all().not()) {
-            return;
+            return false;
         }
 
         let mut ans: Vec<Expr<T>> = Vec::with_capacity(list.len());
         for expr in list.drain(..) {
-            if let Expr::All(All(all)) = expr {
-                ans.extend(all);
+            if let Expr::All(all) = expr {
+                ans.extend(all.into_inner());
             } else {
                 ans.push(expr);
             }
         }
         *list = ans;
+        true
+    }
+
+    fn run<T>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        preorder_mut(expr, |expr| {
+            changed |= match expr {
+                Expr::Any(Any(list)) => Self::flatten_any(list),
+                Expr::All(All(list)) => Self::flatten_all(list),
+                _ => false,
+            }
+        });
+        changed
     }
 }
 
 impl<T> VisitMut<T> for FlattenNestedList {
-    fn visit_mut_any(&mut self, Any(list): &mut Any<T>) {
-        Self::flatten_any(list);
-        walk_mut_expr_list(self, list);
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
     }
 
-    fn visit_mut_all(&mut self, All(list): &mut All<T>) {
-        Self::flatten_all(list);
-        walk_mut_expr_list(self, list);
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
+/// Removes the repeated operands of every `any` and `all`.
 pub struct DedupList;
 
+impl DedupList {
+    fn dedup<T: Eq>(list: &mut Vec<Expr<T>>) -> bool {
+        let len = list.len();
+        let mut i = 0;
+        while i < list.len() {
+            let mut j = i + 1;
+            while j < list.len() {
+                if list[i] == list[j] {
+                    list.remove(j);
+                } else {
+                    j += 1;
+                }
+            }
+            i += 1;
+        }
+        list.len() != len
+    }
+
+    fn run<T: Eq>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        postorder_mut(expr, |expr| {
+            if let Some(list) = expr.as_mut_expr_list() {
+                changed |= Self::dedup(list);
+            }
+        });
+        changed
+    }
+}
+
 impl<T> VisitMut<T> for DedupList
 where
     T: Eq,
 {
     fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
-        if let Some(list) = expr.as_mut_expr_list() {
-            let mut i = 0;
-            while i < list.len() {
-                let mut j = i + 1;
-                while j < list.len() {
-                    if list[i] == list[j] {
-                        list.remove(j);
-                    } else {
-                        j += 1;
-                    }
-                }
-                i += 1;
-            }
-        }
-        walk_mut_expr(self, expr);
+        Self::run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
 pub struct EvalConst;
 
 impl EvalConst {
-    fn eval_any<T>(any: &mut Vec<Expr<T>>) -> Option<bool> {
+    /// Also returns whether `any` has changed.
+    fn eval_any<T>(any: &mut Vec<Expr<T>>) -> (Option<bool>, bool) {
+        let len = any.len();
         any.remove_if(|expr| expr.is_const_false());
+        (Self::eval_any_rest(any), any.len() != len)
+    }
 
+    fn eval_any_rest<T>(any: &[Expr<T>]) -> Option<bool> {
         if any.is_empty() {
             return Some(false);
         }
@@ -142,9 +197,14 @@ impl EvalConst {
         None
     }
 
-    fn eval_all<T>(all: &mut Vec<Expr<T>>) -> Option<bool> {
+    /// Also returns whether `all` has changed.
+    fn eval_all<T>(all: &mut Vec<Expr<T>>) -> (Option<bool>, bool) {
+        let len = all.len();
         all.remove_if(|expr| expr.is_const_true());
+        (Self::eval_all_rest(all), all.len() != len)
+    }
 
+    fn eval_all_rest<T>(all: &[Expr<T>]) -> Option<bool> {
         if all.is_empty() {
             return Some(true);
         }
@@ -164,75 +224,92 @@ impl EvalConst {
     }
 }
 
-impl<T> VisitMut<T> for EvalConst {
-    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
-        walk_mut_expr(self, expr);
-
-        match expr {
-            Expr::Any(Any(any)) => {
-                if let Some(val) = Self::eval_any(any) {
-                    *expr = Expr::Const(val);
-                }
-            }
-            Expr::All(All(all)) => {
-                if let Some(val) = Self::eval_all(all) {
-                    *expr = Expr::Const(val);
-                }
-            }
-            Expr::Not(Not(not)) => {
-                if let Some(val) = Self::eval_not(not) {
-                    *expr = Expr::Const(val);
-                }
+impl EvalConst {
+    /// Returns whether `expr` has changed.
+    fn eval<T>(expr: &mut Expr<T>) -> bool {
+        let (val, changed) = match expr {
+            Expr::Any(Any(any)) => Self::eval_any(any),
+            Expr::All(All(all)) => Self::eval_all(all),
+            Expr::Not(Not(not)) => (Self::eval_not(not), false),
+            _ => (None, false),
+        };
+        match val {
+            Some(val) => {
+                *expr = Expr::Const(val);
+                true
             }
-            _ => {}
+            None => changed,
         }
     }
+
+    pub(crate) fn run<T>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        postorder_mut(expr, |expr| changed |= Self::eval(expr));
+        changed
+    }
 }
 
-pub struct SimplifyNestedList;
+impl<T> VisitMut<T> for EvalConst {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
 
-impl SimplifyNestedList {
-    fn contains_cross_same<T: Eq>(lhs: &[T], rhs: &[T]) -> bool {
-        lhs.iter().any(|x| rhs.contains(x))
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
-impl<T> VisitMut<T> for SimplifyNestedList
-where
-    T: Eq,
-{
-    /// `any(x0, all(x0, x1), x2) => any(x0, x2)`
-    fn visit_mut_any(&mut self, Any(any): &mut Any<T>) {
+/// `any(x0, all(x0, x1), x2) => any(x0, x2)`
+///
+/// `all(x0, any(x0, x1), x2) => all(x0, x2)`
+pub struct SimplifyNestedList;
+
+impl SimplifyNestedList {
+    /// Removes the inner lists which share an element with the outer list.
+    fn remove_absorbed<T: Eq>(list: &mut Vec<Expr<T>>, as_inner: fn(&Expr<T>) -> Option<&[Expr<T>]>) -> bool {
+        let len = list.len();
         let mut i = 0;
-        while i < any.len() {
-            if let Expr::All(All(all)) = &any[i] {
-                if Self::contains_cross_same(all, any) {
-                    any.remove(i);
+        while i < list.len() {
+            if let Some(inner) = as_inner(&list[i]) {
+                if inner.iter().any(|x| list.contains(x)) {
+                    list.remove(i);
                     continue;
                 }
             }
-
             i += 1;
         }
-
-        walk_mut_expr_list(self, any);
+        list.len() != len
     }
 
-    /// `all(x0, any(x0, x1), x2) => all(x0, x2)`
-    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
-        let mut i = 0;
-        while i < all.len() {
-            if let Expr::Any(Any(any)) = &all[i] {
-                if Self::contains_cross_same(any, all) {
-                    all.remove(i);
-                    continue;
-                }
+    fn run<T: Eq>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        preorder_mut(expr, |expr| {
+            changed |= match expr {
+                Expr::Any(Any(any)) => Self::remove_absorbed(any, |x| match x {
+                    Expr::All(All(all)) => Some(all),
+                    _ => None,
+                }),
+                Expr::All(All(all)) => Self::remove_absorbed(all, |x| match x {
+                    Expr::Any(Any(any)) => Some(any),
+                    _ => None,
+                }),
+                _ => false,
             }
+        });
+        changed
+    }
+}
 
-            i += 1;
-        }
+impl<T> VisitMut<T> for SimplifyNestedList
+where
+    T: Eq,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
 
-        walk_mut_expr_list(self, all);
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
@@ -240,7 +317,8 @@ pub struct SimplifyAllNotAny;
 
 impl SimplifyAllNotAny {
     /// Simplify `all(not(any(...)), any(...))`
-    fn counteract<T: Eq>(neg: &[Expr<T>], pos: &mut Vec<Expr<T>>) {
+    fn counteract<T: Eq>(neg: &[Expr<T>], pos: &mut Vec<Expr<T>>) -> bool {
+        let len = pos.len();
         let mut i = 0;
         while i < pos.len() {
             if neg.contains(&pos[i]) {
@@ -249,51 +327,84 @@ impl SimplifyAllNotAny {
                 i += 1;
             }
         }
+        pos.len() != len
     }
-}
 
-impl<T> VisitMut<T> for SimplifyAllNotAny
-where
-    T: Eq,
-{
-    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
-        if let [Expr::Not(Not(not)), Expr::Any(Any(pos))] = all.as_mut_slice() {
+    fn simplify<T: Eq>(all: &mut [Expr<T>]) -> bool {
+        if let [Expr::Not(Not(not)), Expr::Any(Any(pos))] = all {
             let neg = match not.as_mut_any() {
                 Some(Any(neg)) => neg,
                 None => slice::from_mut(&mut **not),
             };
-            Self::counteract(neg, pos);
-        } else if let [Expr::Any(Any(pos)), Expr::Not(Not(not))] = all.as_mut_slice() {
+            Self::counteract(neg, pos)
+        } else if let [Expr::Any(Any(pos)), Expr::Not(Not(not))] = all {
             let neg = match not.as_mut_any() {
                 Some(Any(neg)) => neg,
                 None => slice::from_mut(&mut **not),
             };
-            Self::counteract(neg, pos);
+            Self::counteract(neg, pos)
+        } else {
+            false
         }
+    }
+
+    fn run<T: Eq>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        preorder_mut(expr, |expr| {
+            if let Expr::All(All(all)) = expr {
+                changed |= Self::simplify(all);
+            }
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for SimplifyAllNotAny
+where
+    T: Eq,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
 
-        walk_mut_expr_list(self, all);
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
 pub struct FlattenByDeMorgan;
 
-impl<T> VisitMut<T> for FlattenByDeMorgan {
-    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
-        if let Expr::Not(Not(not)) = expr {
-            match &mut **not {
-                Expr::Any(Any(any)) => {
-                    let list = map_collect_vec(any.drain(..), |expr| ast::expr(ast::not(expr)));
-                    *expr = ast::expr(ast::all(list));
-                }
-                Expr::All(All(all)) => {
-                    let list = map_collect_vec(all.drain(..), |expr| ast::expr(ast::not(expr)));
-                    *expr = ast::expr(ast::any(list));
+impl FlattenByDeMorgan {
+    fn run<T>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        preorder_mut(expr, |expr| {
+            if let Expr::Not(Not(not)) = expr {
+                match &mut **not {
+                    Expr::Any(Any(any)) => {
+                        let list = map_collect_vec(any.drain(..), |expr| ast::expr(ast::not(expr)));
+                        *expr = ast::expr(ast::all(list));
+                        changed = true;
+                    }
+                    Expr::All(All(all)) => {
+                        let list = map_collect_vec(all.drain(..), |expr| ast::expr(ast::not(expr)));
+                        *expr = ast::expr(ast::any(list));
+                        changed = true;
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
-        }
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for FlattenByDeMorgan {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
 
-        walk_mut_expr(self, expr)
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
@@ -305,8 +416,8 @@ impl MergeAllOfNotAny {
     }
 
     fn unwrap_expr_not_var<T>(expr: Expr<T>) -> Var<T> {
-        if let Expr::Not(Not(not)) = expr {
-            if let Expr::Var(var) = *not {
+        if let Expr::Not(not) = expr {
+            if let Expr::Var(var) = not.into_inner() {
                 return var;
             }
         }
@@ -314,14 +425,16 @@ impl MergeAllOfNotAny {
     }
 }
 
-impl<T> VisitMut<T> for MergeAllOfNotAny {
-    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
+impl MergeAllOfNotAny {
+    fn merge<T>(all: &mut Vec<Expr<T>>) -> bool {
+        let mut changed = false;
         let mut not_any_list: Vec<_> = filter_map_collect(&mut *all, Self::as_mut_not_any);
 
         if let [first, rest @ ..] = not_any_list.as_mut_slice() {
             if rest.is_empty().not() {
                 rest.iter_mut().for_each(|x| first.append(x));
-                all.remove_if(|x| x.is_empty_not_any())
+                all.remove_if(|x| x.is_empty_not_any());
+                changed = true;
             }
 
             {
@@ -331,48 +444,99 @@ impl<T> VisitMut<T> for MergeAllOfNotAny {
                 for not_var in not_var_list {
                     let var = Self::unwrap_expr_not_var(not_var);
                     not_any.push(ast::expr(var));
+                    changed = true;
                 }
             }
         }
+        changed
+    }
+
+    /// Merges the outermost `all`s only.
+    fn run<T>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        preorder_mut_while(expr, |expr| match expr {
+            Expr::All(All(all)) => {
+                changed |= Self::merge(all);
+                false
+            }
+            _ => true,
+        });
+        changed
     }
 }
 
+impl<T> VisitMut<T> for MergeAllOfNotAny {
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
+    }
+}
+
+/// Replaces every `any` of an `all` which is implied by a sibling `any` with `any(true)`.
+///
+/// `all(any(x0, x1), any(x0, x1, x2)) => all(any(x0, x1), any(true))`
 pub struct MergeAllOfAny;
 
 impl MergeAllOfAny {
     fn is_subset_of<T: Eq>(lhs: &[Expr<T>], rhs: &[Expr<T>]) -> bool {
         lhs.iter().all(|x| rhs.contains(x))
     }
-}
 
-impl<T: Eq> VisitMut<T> for MergeAllOfAny {
-    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
-        walk_mut_expr_list(self, all);
-
-        let mut any_list: Vec<_> = filter_map_collect(&mut *all, |x| Expr::as_mut_any(x).map(|x| &mut x.0));
+    fn merge<T: Eq>(all: &mut [Expr<T>]) -> bool {
+        let mut changed = false;
+        let mut any_list: Vec<_> = filter_map_collect(all, |x| Expr::as_mut_any(x).map(|x| &mut x.0));
 
         for i in 0..any_list.len() {
             for j in 0..any_list.len() {
                 if let Some((lhs, rhs)) = any_list.get2_mut(i, j) {
                     if Self::is_subset_of(lhs, rhs) {
+                        changed |= !matches!(rhs.as_slice(), [Expr::Const(true)]);
                         rhs.clear();
                         rhs.push(Expr::Const(true));
                     }
                 }
             }
         }
+        changed
+    }
+
+    fn run<T: Eq>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        postorder_mut(expr, |expr| {
+            if let Expr::All(All(all)) = expr {
+                changed |= Self::merge(all);
+            }
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for MergeAllOfAny
+where
+    T: Eq,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
 pub struct SimplifyByShortCircuit;
 
 impl SimplifyByShortCircuit {
-    fn find_vars<T: Eq + Clone>(list: &mut [Expr<T>], marker: bool) -> Vec<Var<T>> {
+    fn find_vars<T: Eq + Clone>(list: &mut [Expr<T>], marker: bool, changed: &mut bool) -> Vec<Var<T>> {
         let mut ans: Vec<Var<T>> = default();
         for x in list {
             if let Expr::Var(var) = x {
                 if ans.contains(var) {
                     *x = Expr::Const(marker);
+                    *changed = true;
                 } else {
                     ans.push(var.clone())
                 }
@@ -381,40 +545,178 @@ impl SimplifyByShortCircuit {
         ans
     }
 
-    fn replace_vars<T: Eq>(x: &mut Expr<T>, vars: &[Var<T>], marker: bool) {
-        match x {
-            Expr::Any(Any(any)) => any.iter_mut().for_each(|x| Self::replace_vars(x, vars, marker)),
-            Expr::All(All(all)) => all.iter_mut().for_each(|x| Self::replace_vars(x, vars, marker)),
-            Expr::Not(Not(not)) => Self::replace_vars(not, vars, marker),
-            Expr::Var(var) => {
+    fn replace_vars<T: Eq>(x: &mut Expr<T>, vars: &[Var<T>], marker: bool, changed: &mut bool) {
+        preorder_mut(x, |x| {
+            if let Expr::Var(var) = x {
                 if vars.contains(var) {
                     *x = Expr::Const(marker);
+                    *changed = true;
                 }
             }
-            Expr::Const(_) => {}
+        })
+    }
+
+    fn simplify<T: Eq + Clone>(list: &mut [Expr<T>], marker: bool, changed: &mut bool) {
+        let vars = Self::find_vars(list, marker, changed);
+        for x in list.iter_mut().filter(|x| x.is_var().not()) {
+            Self::replace_vars(x, &vars, marker, changed);
         }
     }
+
+    fn run<T: Eq + Clone>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        preorder_mut(expr, |expr| match expr {
+            Expr::Any(Any(any)) => Self::simplify(any, false, &mut changed),
+            Expr::All(All(all)) => Self::simplify(all, true, &mut changed),
+            _ => {}
+        });
+        changed
+    }
 }
 
 impl<T: Eq + Clone> VisitMut<T> for SimplifyByShortCircuit {
-    fn visit_mut_any(&mut self, Any(any): &mut Any<T>) {
-        let marker = false;
-        let vars = Self::find_vars(any, marker);
-        for x in any.iter_mut().filter(|x| x.is_var().not()) {
-            Self::replace_vars(x, &vars, marker);
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
+    }
+}
+
+/// Pulls common conjuncts out of disjunctions and common disjuncts out of conjunctions.
+///
+/// `any(all(x0, x1, x2), all(x0, x1, x3)) => all(x0, x1, any(x2, x3))`
+///
+/// `all(any(x0, x1), any(x0, x2)) => any(x0, all(x1, x2))`
+///
+/// The factor which saves the most nodes is pulled out first.
+pub struct Factor;
+
+impl Factor {
+    fn size<T>(expr: &Expr<T>) -> usize {
+        preorder(expr).count()
+    }
+
+    /// Returns the conjuncts (disjuncts) of an operand.
+    fn terms<T>(expr: &Expr<T>, conj: bool) -> &[Expr<T>] {
+        match expr {
+            Expr::All(All(list)) if conj => list,
+            Expr::Any(Any(list)) if !conj => list,
+            _ => slice::from_ref(expr),
         }
+    }
 
-        walk_mut_expr_list(self, any)
+    fn into_terms<T>(expr: Expr<T>, conj: bool) -> Vec<Expr<T>> {
+        match expr {
+            Expr::All(all) if conj => all.into_inner(),
+            Expr::Any(any) if !conj => any.into_inner(),
+            _ => vec![expr],
+        }
     }
 
-    fn visit_mut_all(&mut self, All(all): &mut All<T>) {
-        let marker = true;
-        let vars = Self::find_vars(all, marker);
-        for x in all.iter_mut().filter(|x| x.is_var().not()) {
-            Self::replace_vars(x, &vars, marker);
+    fn join<T>(mut list: Vec<Expr<T>>, conj: bool) -> Expr<T> {
+        if list.len() == 1 {
+            list.pop().unwrap()
+        } else if conj {
+            ast::expr(ast::all(list))
+        } else {
+            ast::expr(ast::any(list))
+        }
+    }
+
+    /// Finds the operands sharing the common terms with the largest saving.
+    fn best_factor<T: Ord + Clone>(list: &[Expr<T>], conj: bool) -> Option<(Vec<usize>, Vec<Expr<T>>)> {
+        let mut best = None;
+        let mut best_saving = 0;
+        let mut tried: BTreeSet<&Expr<T>> = BTreeSet::new();
+
+        for term in list.iter().flat_map(|x| Self::terms(x, conj)) {
+            if tried.insert(term).not() {
+                continue;
+            }
+
+            let group: Vec<usize> = (0..list.len())
+                .filter(|&i| Self::terms(&list[i], conj).contains(term))
+                .collect();
+            if group.len() < 2 {
+                continue;
+            }
+
+            let mut common: Vec<&Expr<T>> = default();
+            for x in Self::terms(&list[group[0]], conj) {
+                if common.contains(&x).not() {
+                    common.push(x);
+                }
+            }
+            for &i in &group[1..] {
+                let terms: BTreeSet<&Expr<T>> = Self::terms(&list[i], conj).iter().collect();
+                common.retain(|x| terms.contains(x));
+            }
+
+            let saving = (group.len() - 1) * common.iter().map(|x| Self::size(x)).sum::<usize>();
+            if saving > best_saving {
+                best_saving = saving;
+                best = Some((group, common));
+            }
         }
 
-        walk_mut_expr_list(self, all)
+        best.map(|(group, common)| (group, common.into_iter().cloned().collect()))
+    }
+
+    fn factor<T: Ord + Clone>(list: &mut Vec<Expr<T>>, conj: bool) -> bool {
+        let mut changed = false;
+        while let Some((group, mut common)) = Self::best_factor(list, conj) {
+            changed = true;
+            let mut rest: Vec<Vec<Expr<T>>> = default();
+            for &i in group.iter().rev() {
+                let mut terms = Self::into_terms(list.remove(i), conj);
+                terms.remove_if(|x| common.contains(x));
+                rest.push(terms);
+            }
+            rest.reverse();
+
+            // an operand without other terms absorbs the rest of the group
+            if rest.iter().all(|x| x.is_empty().not()) {
+                let rest = map_collect_vec(rest, |x| Self::join(x, conj));
+                common.push(Self::join(rest, !conj));
+            }
+
+            list.insert(group[0], Self::join(common, conj));
+        }
+        changed
+    }
+
+    fn run<T: Ord + Clone>(expr: &mut Expr<T>) -> bool {
+        let mut changed = false;
+        preorder_mut(expr, |expr| {
+            changed |= match expr {
+                Expr::Any(Any(list)) => Self::factor(list, true),
+                Expr::All(All(list)) => Self::factor(list, false),
+                _ => false,
+            };
+
+            if let Some(list) = expr.as_mut_expr_list() {
+                if list.len() == 1 {
+                    *expr = list.pop().unwrap();
+                    changed = true;
+                }
+            }
+        });
+        changed
+    }
+}
+
+impl<T> VisitMut<T> for Factor
+where
+    T: Ord + Clone,
+{
+    fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
+        Self::run(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool {
+        Self::run(expr)
     }
 }
 
@@ -430,4 +732,50 @@ mod tests {
         EvalConst.visit_mut_expr(&mut cfg);
         assert_eq!(cfg.to_string(), "false");
     }
+
+    #[test]
+    fn list_passes() {
+        let (x0, x1, x2) = (var(0), var(1), var(2));
+
+        // the operands are deduplicated before their parent
+        let mut x: Expr<u32> = expr(any((any((x0, x0)), any((x0,)), all((x1, x2, x1)))));
+        DedupList.visit_mut_expr(&mut x);
+        assert_eq!(x.to_string(), "any(any(0), all(1, 2))");
+
+        let mut x: Expr<u32> = expr(any((x0, all((x1, any((x2, all((x2, x0)))))), all((x0, x1)))));
+        SimplifyNestedList.visit_mut_expr(&mut x);
+        assert_eq!(x.to_string(), "any(0, all(1, any(2)))");
+
+        let mut x: Expr<u32> = expr(all((any((x0, x1)), any((x1, x2, x0)), not(any((x0,))))));
+        MergeAllOfAny.visit_mut_expr(&mut x);
+        assert_eq!(x.to_string(), "all(any(0, 1), any(true), not(any(0)))");
+    }
+
+    #[test]
+    fn factor() {
+        let (x0, x1, x2, x3, x4) = (var(0), var(1), var(2), var(3), var(4));
+        let check = |mut src: Expr<u32>, expected: &str| {
+            Factor.visit_mut_expr(&mut src);
+            assert_eq!(src.to_string(), expected);
+        };
+
+        check(
+            expr(any((all((x0, x1, x2)), all((x0, x1, x3))))),
+            "all(0, 1, any(2, 3))",
+        );
+        check(
+            expr(all((any((x0, x1)), any((x0, x2)), x3))),
+            "all(any(0, all(1, 2)), 3)",
+        );
+        check(expr(any((x0, all((x0, x1)), x2))), "any(0, 2)");
+        check(
+            expr(any((
+                all((x0, x3)),
+                all((x0, x4)),
+                all((x1, x2, x3)),
+                all((x1, x2, x4)),
+            ))),
+            "all(any(3, 4), any(0, all(1, 2)))",
+        );
+    }
 }
@@ -1,10 +1,21 @@
+#[cfg(feature = "std")]
+use crate::ast::{Expr, Var};
+#[cfg(feature = "std")]
+use crate::visit::preorder;
+
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::hash::Hash;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
+
 /// TODO: move to rust_utils
 pub fn drain_filter<'a, T, F>(v: &'a mut Vec<T>, mut f: F) -> impl Iterator<Item = T> + 'a
 where
     F: FnMut(&mut T) -> bool + 'a,
 {
     let mut i = 0;
-    std::iter::from_fn(move || {
+    core::iter::from_fn(move || {
         while i < v.len() {
             if f(&mut v[i]) {
                 return Some(v.remove(i));
@@ -15,3 +26,21 @@ where
         None
     })
 }
+
+/// Returns the variables of `expr` in the order of their first appearance.
+#[cfg(feature = "std")]
+pub fn collect_vars<T>(expr: &Expr<T>) -> Vec<&T>
+where
+    T: Eq + Hash,
+{
+    let mut seen = HashSet::new();
+    let mut ans = Vec::new();
+    for x in preorder(expr) {
+        if let Expr::Var(Var(var)) = x {
+            if seen.insert(var) {
+                ans.push(var);
+            }
+        }
+    }
+    ans
+}
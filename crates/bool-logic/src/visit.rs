@@ -0,0 +1,157 @@
+use crate::ast::{All, Any, Expr, Not, Var};
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::slice;
+
+/// A visitor of an expression by reference.
+///
+/// The `walk_*` functions recurse once per level of nesting, so a visitor built on them is limited by the call stack.
+/// Use [`preorder`] for very deep expressions.
+#[allow(unused_variables)]
+pub trait Visit<'a, T> {
+    fn visit_expr(&mut self, expr: &'a Expr<T>) {
+        walk_expr(self, expr)
+    }
+
+    fn visit_any(&mut self, Any(any): &'a Any<T>) {
+        walk_expr_list(self, any);
+    }
+
+    fn visit_all(&mut self, All(all): &'a All<T>) {
+        walk_expr_list(self, all);
+    }
+
+    fn visit_not(&mut self, Not(not): &'a Not<T>) {
+        walk_not(self, not)
+    }
+
+    fn visit_var(&mut self, var: &'a Var<T>) {}
+
+    fn visit_const(&mut self, b: &'a bool) {}
+}
+
+pub fn walk_expr<'a, V, T>(v: &mut V, expr: &'a Expr<T>)
+where
+    V: Visit<'a, T> + ?Sized,
+{
+    match expr {
+        Expr::Any(any) => {
+            v.visit_any(any);
+        }
+        Expr::All(all) => {
+            v.visit_all(all);
+        }
+        Expr::Not(not) => {
+            v.visit_not(not);
+        }
+        Expr::Var(var) => {
+            v.visit_var(var);
+        }
+        Expr::Const(b) => {
+            v.visit_const(b);
+        }
+    }
+}
+
+pub fn walk_expr_list<'a, V, T>(v: &mut V, list: &'a [Expr<T>])
+where
+    V: Visit<'a, T> + ?Sized,
+{
+    for expr in list {
+        v.visit_expr(expr);
+    }
+}
+
+pub fn walk_not<'a, V, T>(v: &mut V, not: &'a Expr<T>)
+where
+    V: Visit<'a, T> + ?Sized,
+{
+    v.visit_expr(not);
+}
+
+/// Returns an iterator over the subexpressions of `expr` in pre-order, starting with `expr` itself.
+///
+/// Unlike [`Visit`], the iterator keeps its own stack, so it works on arbitrarily deep expressions.
+pub fn preorder<T>(expr: &Expr<T>) -> Preorder<'_, T> {
+    Preorder { stack: vec![expr] }
+}
+
+pub struct Preorder<'a, T> {
+    stack: Vec<&'a Expr<T>>,
+}
+
+impl<'a, T> Iterator for Preorder<'a, T> {
+    type Item = &'a Expr<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let expr = self.stack.pop()?;
+        match expr {
+            Expr::Any(Any(list)) | Expr::All(All(list)) => self.stack.extend(list.iter().rev()),
+            Expr::Not(Not(not)) => self.stack.push(not),
+            Expr::Var(_) | Expr::Const(_) => {}
+        }
+        Some(expr)
+    }
+}
+
+/// Folds `expr` bottom-up without recursion: `f` receives each subexpression with the results of its operands.
+pub(crate) fn postorder_with<'a, T, R, F>(expr: &'a Expr<T>, mut f: F) -> R
+where
+    F: FnMut(&'a Expr<T>, Vec<R>) -> R,
+{
+    struct Frame<'a, T, R> {
+        expr: &'a Expr<T>,
+        rest: slice::Iter<'a, Expr<T>>,
+        results: Vec<R>,
+    }
+
+    let frame = |expr: &'a Expr<T>| Frame {
+        expr,
+        rest: expr.operands().iter(),
+        results: Vec::new(),
+    };
+    let mut stack = vec![frame(expr)];
+    loop {
+        let top = stack.last_mut().unwrap();
+        if let Some(next) = top.rest.next() {
+            stack.push(frame(next));
+            continue;
+        }
+        let Frame { expr, results, .. } = stack.pop().unwrap();
+        let result = f(expr, results);
+        match stack.last_mut() {
+            Some(parent) => parent.results.push(result),
+            None => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::ast::*;
+
+    #[test]
+    fn depth() {
+        struct Depth {
+            cur: usize,
+            max: usize,
+        }
+
+        impl<'a, T> Visit<'a, T> for Depth {
+            fn visit_expr(&mut self, expr: &'a Expr<T>) {
+                self.cur += 1;
+                self.max = self.max.max(self.cur);
+                walk_expr(self, expr);
+                self.cur -= 1;
+            }
+        }
+
+        let x: Expr<u32> = expr(all((var(0), not(any((var(1), var(2)))))));
+        let mut v = Depth { cur: 0, max: 0 };
+        v.visit_expr(&x);
+        assert_eq!(v.max, 4);
+    }
+}
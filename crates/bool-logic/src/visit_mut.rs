@@ -1,5 +1,14 @@
 use crate::ast::{All, Any, Expr, Not, Var};
 
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem;
+
+/// A visitor of an expression by mutable reference.
+///
+/// The `walk_mut_*` functions recurse once per level of nesting, so a visitor built on them is limited by the call stack.
+/// The passes in this crate override [`visit_mut_expr`](Self::visit_mut_expr)
+/// with [`preorder_mut`] or [`postorder_mut`] instead, which keep their own stack.
 #[allow(unused_variables)]
 pub trait VisitMut<T> {
     fn visit_mut_expr(&mut self, expr: &mut Expr<T>) {
@@ -21,6 +30,19 @@ pub trait VisitMut<T> {
     fn visit_mut_var(&mut self, var: &mut Var<T>) {}
 
     fn visit_mut_const(&mut self, b: &mut bool) {}
+
+    /// Visits `expr` and returns whether it has changed.
+    ///
+    /// The default implementation compares `expr` with a copy taken before the visit.
+    /// The passes in this crate override it to report their changes without the copy.
+    fn visit_mut_changed(&mut self, expr: &mut Expr<T>) -> bool
+    where
+        T: Clone + PartialEq,
+    {
+        let before = expr.clone();
+        self.visit_mut_expr(expr);
+        *expr != before
+    }
 }
 
 pub fn walk_mut_expr<V, T>(v: &mut V, expr: &mut Expr<T>)
@@ -61,3 +83,119 @@ where
 {
     v.visit_mut_expr(not);
 }
+
+/// Calls `f` on every subexpression of `expr` before its operands, starting with `expr` itself.
+///
+/// The operands visited are those of the subexpression after `f` has returned.
+/// The traversal keeps its own stack, so it works on arbitrarily deep expressions.
+pub fn preorder_mut<T, F>(expr: &mut Expr<T>, mut f: F)
+where
+    F: FnMut(&mut Expr<T>),
+{
+    traverse_mut(
+        expr,
+        |expr| {
+            f(expr);
+            true
+        },
+        |_, _: Vec<()>, _| {},
+    )
+}
+
+/// Like [`preorder_mut`], but skips the operands of every subexpression for which `f` returns `false`.
+pub fn preorder_mut_while<T, F>(expr: &mut Expr<T>, f: F)
+where
+    F: FnMut(&mut Expr<T>) -> bool,
+{
+    traverse_mut(expr, f, |_, _: Vec<()>, _| {})
+}
+
+/// Calls `f` on every subexpression of `expr` after its operands, ending with `expr` itself.
+///
+/// The traversal keeps its own stack, so it works on arbitrarily deep expressions.
+pub fn postorder_mut<T, F>(expr: &mut Expr<T>, mut f: F)
+where
+    F: FnMut(&mut Expr<T>),
+{
+    traverse_mut(expr, |_| true, |expr, _: Vec<()>, _| f(expr))
+}
+
+/// The traversal behind [`preorder_mut`] and [`postorder_mut`].
+///
+/// The operands of a subexpression are skipped if `pre` returns `false`.
+/// `post` also receives the results of the operands and the operand indices from the root to the subexpression.
+pub(crate) fn traverse_mut<T, R, Pre, Post>(expr: &mut Expr<T>, mut pre: Pre, mut post: Post) -> R
+where
+    Pre: FnMut(&mut Expr<T>) -> bool,
+    Post: FnMut(&mut Expr<T>, Vec<R>, &[usize]) -> R,
+{
+    struct Frame<T, R> {
+        parent: Expr<T>,
+        done: Vec<Expr<T>>,
+        results: Vec<R>,
+        rest: vec::IntoIter<Expr<T>>,
+    }
+
+    let mut stack: Vec<Frame<T, R>> = Vec::new();
+    let mut path: Vec<usize> = Vec::new();
+    let mut cur = mem::replace(expr, Expr::Const(false));
+    let result = 'descend: loop {
+        let mut operands = if pre(&mut cur) { detach(&mut cur) } else { Vec::new() }.into_iter();
+        if let Some(first) = operands.next() {
+            let len = operands.len() + 1;
+            stack.push(Frame {
+                parent: cur,
+                done: Vec::with_capacity(len),
+                results: Vec::with_capacity(len),
+                rest: operands,
+            });
+            path.push(0);
+            cur = first;
+            continue 'descend;
+        }
+        let mut result = post(&mut cur, Vec::new(), &path);
+
+        loop {
+            let Some(frame) = stack.last_mut() else {
+                break 'descend result;
+            };
+            frame.done.push(cur);
+            frame.results.push(result);
+            if let Some(next) = frame.rest.next() {
+                *path.last_mut().unwrap() += 1;
+                cur = next;
+                continue 'descend;
+            }
+            path.pop();
+            let Frame {
+                mut parent,
+                done,
+                results,
+                ..
+            } = stack.pop().unwrap();
+            attach(&mut parent, done);
+            result = post(&mut parent, results, &path);
+            cur = parent;
+        }
+    };
+    *expr = cur;
+    result
+}
+
+/// Moves the operands out of `expr`.
+pub(crate) fn detach<T>(expr: &mut Expr<T>) -> Vec<Expr<T>> {
+    match expr {
+        Expr::Any(Any(list)) | Expr::All(All(list)) => mem::take(list),
+        Expr::Not(Not(not)) => vec![mem::replace(&mut **not, Expr::Const(false))],
+        Expr::Var(_) | Expr::Const(_) => Vec::new(),
+    }
+}
+
+/// Puts the operands moved out by [`detach`] back.
+pub(crate) fn attach<T>(expr: &mut Expr<T>, mut operands: Vec<Expr<T>>) {
+    match expr {
+        Expr::Any(Any(list)) | Expr::All(All(list)) => *list = operands,
+        Expr::Not(Not(not)) => **not = operands.pop().unwrap(),
+        Expr::Var(_) | Expr::Const(_) => {}
+    }
+}
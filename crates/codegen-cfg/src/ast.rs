@@ -23,7 +23,7 @@ pub fn not(x: impl Into<Not<Pred>>) -> Not<Pred> {
     x.into()
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Pred {
     pub key: String,
     pub value: Option<String>,
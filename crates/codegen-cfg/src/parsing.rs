@@ -27,7 +27,7 @@ macro_rules! ensure {
     };
 }
 
-pub fn parse(mut s: &str) -> Result<Expr> {
+pub fn parse(mut s: &str) -> Result<'_, Expr> {
     let s = &mut s;
     skip_space0(s);
     let expr = parse_expr(s)?;
@@ -3,6 +3,6 @@ mod syn;
 mod transform;
 
 pub use self::search::{search, CfgItem};
-pub use self::transform::simplified_expr;
+pub use self::transform::{explained_simplified_expr, simplified_expr};
 
 pub use regex::RegexSet;
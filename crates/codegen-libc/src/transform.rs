@@ -1,9 +1,12 @@
 use codegen_cfg::ast::*;
+use codegen_cfg::bool_logic::axioms::{Axioms, ImplyByAxioms};
+use codegen_cfg::bool_logic::explain::Trace;
+use codegen_cfg::bool_logic::pipeline::Pipeline;
 use codegen_cfg::bool_logic::transform::*;
+use codegen_cfg::bool_logic::visit::preorder;
 use codegen_cfg::bool_logic::visit_mut::*;
 use log::debug;
 use rust_utils::iter::filter_map_collect_vec;
-use rust_utils::iter::map_collect_vec;
 use rust_utils::vec::VecExt;
 
 use std::cmp::Ordering::{self, *};
@@ -12,74 +15,70 @@ use std::mem;
 use log::trace;
 
 pub fn simplified_expr(x: impl Into<Expr>) -> Expr {
-    let mut x = x.into();
-
-    debug!("input:                              {x}");
-
-    UnifyTargetFamily.visit_mut_expr(&mut x);
-    trace!("after  UnifyTargetFamily:           {x}");
-
-    for _ in 0..3 {
-        FlattenSingle.visit_mut_expr(&mut x);
-        trace!("after  FlattenSingle:               {x}");
-
-        FlattenNestedList.visit_mut_expr(&mut x);
-        trace!("after  FlattenNestedList:           {x}");
-
-        DedupList.visit_mut_expr(&mut x);
-        trace!("after  DedupList:                   {x}");
-
-        EvalConst.visit_mut_expr(&mut x);
-        trace!("after  EvalConst:                   {x}");
-
-        SimplifyNestedList.visit_mut_expr(&mut x);
-        trace!("after  SimplifyNestedList:          {x}");
-
-        MergeAllOfNotAny.visit_mut_expr(&mut x);
-        trace!("after  MergeAllOfNotAny:            {x}");
-
-        SimplifyAllNotAny.visit_mut_expr(&mut x);
-        trace!("after  SimplifyAllNotAny:           {x}");
-
-        MergeAllOfAny.visit_mut_expr(&mut x);
-        trace!("after  MergeAllOfAny:               {x}");
-
-        ImplyByKey.visit_mut_expr(&mut x);
-        trace!("after  ImplyByKey:                  {x}");
-
-        SuppressTargetFamily.visit_mut_expr(&mut x);
-        trace!("after  SuppressTargetFamily:        {x}");
-
-        EvalConst.visit_mut_expr(&mut x);
-        trace!("after  EvalConst:                   {x}");
-
-        MergePattern.visit_mut_expr(&mut x);
-        trace!("after  MergePattern:                {x}");
+    simplify(x.into(), None)
+}
 
-        EvalConst.visit_mut_expr(&mut x);
-        trace!("after  EvalConst:                   {x}");
+/// Simplifies `x` like [`simplified_expr`] and records the passes which changed it.
+pub fn explained_simplified_expr(x: impl Into<Expr>) -> (Expr, Trace<Pred>) {
+    let mut trace = Trace::new();
+    let x = simplify(x.into(), Some(&mut trace));
+    (x, trace)
+}
 
-        SimplifyByShortCircuit.visit_mut_expr(&mut x);
-        trace!("after  SimplifyByShortCircuit:      {x}");
+fn simplify(mut x: Expr, mut trace: Option<&mut Trace<Pred>>) -> Expr {
+    debug!("input:                              {x}");
 
-        EvalConst.visit_mut_expr(&mut x);
-        trace!("after  EvalConst:                   {x}");
+    run_pass("UnifyTargetFamily", &mut UnifyTargetFamily, &mut x, &mut trace);
+
+    let mut pipeline = Pipeline::new()
+        .pass(FlattenSingle)
+        .pass(FlattenNestedList)
+        .pass(DedupList)
+        .pass(EvalConst)
+        .pass(SimplifyNestedList)
+        .pass(MergeAllOfNotAny)
+        .pass(SimplifyAllNotAny)
+        .pass(MergeAllOfAny)
+        .pass(ImplyByKey)
+        .pass(SuppressTargetFamily)
+        .pass(EvalConst)
+        .pass(MergePattern)
+        .pass(EvalConst)
+        .pass(SimplifyByShortCircuit)
+        .pass(EvalConst);
+    let stats = match trace.as_deref_mut() {
+        Some(trace) => pipeline.run_traced(&mut x, trace),
+        None => pipeline.run_with(&mut x, |name, x| trace!("after  {:<28} {x}", format!("{name}:"))),
+    };
+
+    for pass in stats.fired() {
+        trace!("pass {:<24} changed {}/{} runs", pass.name, pass.changes, pass.runs);
+    }
+    if !stats.fixpoint {
+        debug!("no fixpoint after {} rounds", stats.rounds);
     }
 
-    SimplifyTargetFamily.visit_mut_expr(&mut x);
-    trace!("after  SimplifyTargetFamily:        {x}");
-
-    SortByPriority.visit_mut_expr(&mut x);
-    trace!("after  SortByPriority:              {x}");
-
-    SortByValue.visit_mut_expr(&mut x);
-    trace!("after  SortByValue:                 {x}");
+    run_pass("SimplifyTargetFamily", &mut SimplifyTargetFamily, &mut x, &mut trace);
+    run_pass("SortByPriority", &mut SortByPriority, &mut x, &mut trace);
+    run_pass("SortByValue", &mut SortByValue, &mut x, &mut trace);
 
     debug!("output:                             {x}");
 
     x
 }
 
+fn run_pass(name: &str, pass: &mut impl VisitMut<Pred>, x: &mut Expr, trace: &mut Option<&mut Trace<Pred>>) {
+    match trace {
+        Some(trace) => {
+            let before = x.clone();
+            pass.visit_mut_expr(x);
+            trace.record(name, &before, x);
+        }
+        None => pass.visit_mut_expr(x),
+    }
+    trace!("after  {:<28} {x}", format!("{name}:"));
+}
+
 struct SortByPriority;
 
 impl SortByPriority {
@@ -104,15 +103,19 @@ impl SortByPriority {
 
 impl VisitMut<Pred> for SortByPriority {
     fn visit_mut_expr(&mut self, expr: &mut Expr) {
-        if let Some(list) = expr.as_mut_expr_list() {
-            list.sort_by(|lhs, rhs| {
-                let Some(lhs) = Self::get_priority(lhs) else {return Equal};
-                let Some(rhs) = Self::get_priority(rhs) else {return Equal};
-                lhs.cmp(&rhs)
-            })
-        }
-
-        walk_mut_expr(self, expr);
+        preorder_mut(expr, |expr| {
+            if let Some(list) = expr.as_mut_expr_list() {
+                list.sort_by(|lhs, rhs| {
+                    let Some(lhs) = Self::get_priority(lhs) else {
+                        return Equal;
+                    };
+                    let Some(rhs) = Self::get_priority(rhs) else {
+                        return Equal;
+                    };
+                    lhs.cmp(&rhs)
+                })
+            }
+        })
     }
 }
 
@@ -143,36 +146,44 @@ impl SortByValue {
 
 impl VisitMut<Pred> for SortByValue {
     fn visit_mut_expr(&mut self, expr: &mut Expr) {
-        if let Some(list) = expr.as_mut_expr_list() {
-            list.sort_by(Self::cmp_var);
-            list.sort_by(Self::cmp_not);
-        }
-
-        walk_mut_expr(self, expr);
+        preorder_mut(expr, |expr| {
+            if let Some(list) = expr.as_mut_expr_list() {
+                list.sort_by(Self::cmp_var);
+                list.sort_by(Self::cmp_not);
+            }
+        })
     }
 }
 
 struct UnifyTargetFamily;
 
 impl VisitMut<Pred> for UnifyTargetFamily {
-    fn visit_mut_var(&mut self, Var(pred): &mut Var<Pred>) {
-        if pred.value.is_none() && matches!(pred.key.as_str(), "unix" | "windows" | "wasm") {
-            *pred = key_value("target_family", pred.key.as_str());
-        }
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        preorder_mut(expr, |expr| {
+            if let Expr::Var(Var(pred)) = expr {
+                if pred.value.is_none() && matches!(pred.key.as_str(), "unix" | "windows" | "wasm") {
+                    *pred = key_value("target_family", pred.key.as_str());
+                }
+            }
+        })
     }
 }
 
 struct SimplifyTargetFamily;
 
 impl VisitMut<Pred> for SimplifyTargetFamily {
-    fn visit_mut_var(&mut self, Var(pred): &mut Var<Pred>) {
-        if pred.key == "target_family" {
-            if let Some(value) = pred.value.as_deref() {
-                if matches!(value, "unix" | "windows" | "wasm") {
-                    *pred = flag(value);
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        preorder_mut(expr, |expr| {
+            if let Expr::Var(Var(pred)) = expr {
+                if pred.key == "target_family" {
+                    if let Some(value) = pred.value.as_deref() {
+                        if matches!(value, "unix" | "windows" | "wasm") {
+                            *pred = flag(value);
+                        }
+                    }
                 }
             }
-        }
+        })
     }
 }
 
@@ -188,72 +199,37 @@ impl ImplyByKey {
         "target_pointer_width",
     ];
 
-    fn is_expr_any_pred(any: &[Expr], key: &str) -> bool {
-        any.iter().all(|x| x.as_var().map_or(false, |Var(var)| var.key == key))
-    }
-
-    fn fix(pos_key: &str, pos_any_values: &[&str], expr: &mut Expr) {
-        match expr {
-            Expr::Any(Any(any)) => {
-                any.iter_mut().for_each(|x| Self::fix(pos_key, pos_any_values, x));
-            }
-            Expr::All(All(all)) => {
-                all.iter_mut().for_each(|x| Self::fix(pos_key, pos_any_values, x));
-            }
-            Expr::Not(Not(not)) => {
-                Self::fix(pos_key, pos_any_values, not);
-            }
-            Expr::Var(Var(var)) => {
-                if var.key == pos_key {
-                    let var_value = var.value.as_deref().unwrap();
-                    if pos_any_values.contains(&var_value) {
-                        if pos_any_values.len() == 1 {
-                            *expr = Expr::Const(true)
-                        }
-                    } else {
-                        *expr = Expr::Const(false)
+    fn collect_preds(expr: &Expr, groups: &mut [Vec<Pred>]) {
+        for x in preorder(expr) {
+            if let Expr::Var(Var(pred)) = x {
+                if let Some(i) = Self::UNIQUE_VALUED_KEYS.iter().position(|k| pred.key == *k) {
+                    if !groups[i].contains(pred) {
+                        groups[i].push(pred.clone());
                     }
                 }
             }
-            Expr::Const(_) => {}
         }
     }
+
+    /// The values of a unique-valued key are mutually exclusive.
+    fn axioms(expr: &Expr) -> Axioms<Pred> {
+        let mut groups = vec![Vec::new(); Self::UNIQUE_VALUED_KEYS.len()];
+        Self::collect_preds(expr, &mut groups);
+        groups
+            .into_iter()
+            .filter(|g| g.len() > 1)
+            .fold(Axioms::new(), Axioms::at_most_one)
+    }
 }
 
 impl VisitMut<Pred> for ImplyByKey {
-    fn visit_mut_all(&mut self, All(all): &mut All<Pred>) {
-        walk_mut_expr_list(self, all);
-
-        let mut i = 0;
-        while i < all.len() {
-            match &all[i] {
-                Expr::Var(Var(pos)) => {
-                    if Self::UNIQUE_VALUED_KEYS.contains(&pos.key.as_str()) {
-                        assert!(pos.value.is_some());
-
-                        let pos = pos.clone();
-                        let pos_key = pos.key.as_str();
-                        let pos_any_values = &[pos.value.as_deref().unwrap()];
-
-                        for (_, x) in all.iter_mut().enumerate().filter(|&(j, _)| j != i) {
-                            Self::fix(pos_key, pos_any_values, x);
-                        }
-                    }
-                }
-                Expr::Any(Any(any)) => {
-                    if let Some(pos_key) = Self::UNIQUE_VALUED_KEYS.iter().find(|k| Self::is_expr_any_pred(any, k)) {
-                        let any = any.clone();
-                        let pos_any_values = map_collect_vec(&any, |x| x.as_var().unwrap().0.value.as_deref().unwrap());
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        self.visit_mut_changed(expr);
+    }
 
-                        for (_, x) in all.iter_mut().enumerate().filter(|&(j, _)| j != i) {
-                            Self::fix(pos_key, &pos_any_values, x);
-                        }
-                    }
-                }
-                _ => {}
-            }
-            i += 1;
-        }
+    fn visit_mut_changed(&mut self, expr: &mut Expr) -> bool {
+        let axioms = Self::axioms(expr);
+        ImplyByAxioms::new(&axioms).visit_mut_changed(expr)
     }
 }
 
@@ -290,26 +266,36 @@ impl SuppressTargetFamily {
 }
 
 impl VisitMut<Pred> for SuppressTargetFamily {
-    fn visit_mut_all(&mut self, All(all): &mut All<Pred>) {
-        if all.iter().any(Self::has_specified_target_os) {
-            all.remove_if(|x| match x {
-                Expr::Var(Var(pred)) => Self::is_suppressed_target_family(pred),
-                Expr::Not(Not(not)) => match &**not {
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        self.visit_mut_changed(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr) -> bool {
+        let mut changed = false;
+        preorder_mut(expr, |expr| {
+            let Expr::All(All(all)) = expr else { return };
+            if all.iter().any(Self::has_specified_target_os) {
+                let len = all.len();
+                all.remove_if(|x| match x {
                     Expr::Var(Var(pred)) => Self::is_suppressed_target_family(pred),
+                    Expr::Not(Not(not)) => match &**not {
+                        Expr::Var(Var(pred)) => Self::is_suppressed_target_family(pred),
+                        _ => false,
+                    },
                     _ => false,
-                },
-                _ => false,
-            })
-        }
-
-        walk_mut_expr_list(self, all)
+                });
+                changed |= all.len() != len;
+            }
+        });
+        changed
     }
 }
 
 struct MergePattern;
 
 impl MergePattern {
-    fn merge(any_list: &mut [Expr]) {
+    fn merge(any_list: &mut [Expr]) -> bool {
+        let mut changed = false;
         let mut pattern_list = filter_map_collect_vec(any_list, |x| {
             if let Expr::All(All(all)) = x {
                 if let [first, second] = all.as_mut_slice() {
@@ -341,23 +327,41 @@ impl MergePattern {
                 };
 
                 match mem::replace(to_agg, Expr::Const(false)) {
-                    Expr::Any(Any(any)) => agg.extend(any),
+                    Expr::Any(any) => agg.extend(any.into_inner()),
                     Expr::Var(var) => agg.push(expr(var.clone())),
-                    other => *to_agg = other,
+                    other => {
+                        *to_agg = other;
+                        continue;
+                    }
                 }
+                changed = true;
             }
 
             if agg.len() == 1 {
                 *head.0 = agg.pop().unwrap();
             }
         }
+        changed
     }
 }
 
 impl VisitMut<Pred> for MergePattern {
-    fn visit_mut_any(&mut self, Any(any_list): &mut Any<Pred>) {
-        Self::merge(any_list);
-        Self::merge(&mut any_list[1..]);
+    /// Merges the outermost `any`s only.
+    fn visit_mut_expr(&mut self, expr: &mut Expr) {
+        self.visit_mut_changed(expr);
+    }
+
+    fn visit_mut_changed(&mut self, expr: &mut Expr) -> bool {
+        let mut changed = false;
+        preorder_mut_while(expr, |expr| match expr {
+            Expr::Any(Any(any_list)) => {
+                changed |= Self::merge(any_list);
+                changed |= Self::merge(&mut any_list[1..]);
+                false
+            }
+            _ => true,
+        });
+        changed
     }
 }
 
@@ -391,4 +395,97 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn preserve_semantics() {
+        use codegen_cfg::bool_logic::axioms::Axioms;
+        use codegen_cfg::bool_logic::testing::{Checker, Generator};
+
+        let os = ["linux", "android", "macos"].map(target_os);
+        let preds = [
+            flag("unix"),
+            target_arch("x86"),
+            os[0].clone(),
+            os[1].clone(),
+            os[2].clone(),
+        ];
+        let axioms = Axioms::new().at_most_one(os);
+
+        let checker = Checker::new(Generator::new(preds).consts(true)).cases(500);
+        checker.check(&mut MergePattern).unwrap();
+
+        let checker = checker.assuming(axioms.to_expr());
+        checker.check(&mut ImplyByKey).unwrap();
+    }
+
+    #[test]
+    fn explain() {
+        let input = expr(all((target_os("linux"), any((target_os("linux"), target_os("macos"))))));
+        let (output, trace) = explained_simplified_expr(input.clone());
+        assert_eq!(output, simplified_expr(input));
+        assert_eq!(output.to_string(), r#"target_os = "linux""#);
+
+        let rules: Vec<&str> = trace.events().iter().map(|e| e.rule.as_str()).collect();
+        assert_eq!(rules, ["SimplifyNestedList", "FlattenSingle"]);
+    }
+
+    #[test]
+    fn golden() {
+        let cases = [
+            (
+                r#"all(unix, all(not(any(target_os = "fuchsia", target_os = "switch")), any(target_os = "linux", target_os = "l4re", target_os = "android", target_os = "emscripten")))"#,
+                r#"any(target_os = "android", target_os = "emscripten", target_os = "l4re", target_os = "linux")"#,
+            ),
+            (
+                r#"all(unix, any(target_os = "linux", target_os = "android"), target_os = "linux", target_env = "gnu", any(target_arch = "x86_64", target_arch = "aarch64"), target_arch = "x86_64", target_pointer_width = "64")"#,
+                r#"all(target_arch = "x86_64", target_env = "gnu", target_os = "linux", target_pointer_width = "64")"#,
+            ),
+            (
+                r#"any(all(unix, target_os = "linux", target_env = "gnu"), all(unix, target_os = "linux", target_env = "musl"), all(unix, target_os = "android"))"#,
+                r#"any(target_os = "android", all(target_env = "gnu", target_os = "linux"), all(target_env = "musl", target_os = "linux"))"#,
+            ),
+            (
+                r#"all(unix, any(target_os = "macos", target_os = "ios"), not(target_os = "ios"))"#,
+                r#"target_os = "macos""#,
+            ),
+            (
+                r#"any(all(windows, target_env = "msvc"), all(windows, target_env = "gnu"))"#,
+                r#"any(all(windows, target_env = "msvc"), all(windows, target_env = "gnu"))"#,
+            ),
+            (
+                r#"all(unix, any(target_os = "freebsd", target_os = "dragonfly"), not(any(target_os = "freebsd", target_os = "netbsd")))"#,
+                r#"target_os = "dragonfly""#,
+            ),
+            (
+                r#"all(target_os = "linux", all(not(any(target_pointer_width = "32")), target_pointer_width = "64"))"#,
+                r#"all(target_os = "linux", target_pointer_width = "64")"#,
+            ),
+            (
+                r#"any(all(unix, target_os = "linux", target_arch = "x86_64"), all(unix, target_os = "linux", target_arch = "aarch64"), all(unix, target_os = "android", target_arch = "aarch64"))"#,
+                r#"any(all(target_arch = "x86_64", target_os = "linux"), all(target_arch = "aarch64", any(target_os = "android", target_os = "linux")))"#,
+            ),
+            (
+                r#"all(unix, not(target_os = "emscripten"), any(target_os = "linux", target_os = "android"), not(all(target_os = "linux", target_env = "uclibc")))"#,
+                r#"all(any(target_os = "android", target_os = "linux"), not(all(target_env = "uclibc", target_os = "linux")))"#,
+            ),
+            (
+                r#"all(windows, not(any(target_env = "gnu")), target_env = "msvc", any(target_pointer_width = "64", target_arch = "x86"))"#,
+                r#"all(windows, target_env = "msvc", any(target_arch = "x86", target_pointer_width = "64"))"#,
+            ),
+            // implications under `any`, which the key-based rule did not apply
+            (
+                r#"any(not(target_os = "linux"), target_os = "macos")"#,
+                r#"not(target_os = "linux")"#,
+            ),
+            (
+                r#"all(unix, any(not(target_os = "linux"), all(target_os = "android", target_arch = "x86")))"#,
+                r#"all(unix, not(target_os = "linux"))"#,
+            ),
+        ];
+
+        for (input, output) in cases {
+            let x = codegen_cfg::parsing::parse(input).unwrap();
+            assert_eq!(simplified_expr(x).to_string(), output, "{input}");
+        }
+    }
 }